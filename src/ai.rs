@@ -1,154 +1,589 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, tasks::ComputeTaskPool};
 
+pub mod avoidance;
+pub mod bfm;
+pub mod lod;
+pub mod orders;
+pub mod sensors;
+pub mod skill;
+pub mod state;
+pub mod tactics;
+pub mod targeting;
+pub mod utility;
+
+use super::autopilot::{Autopilot, LateralMode, VerticalMode};
+use super::damage::{AircraftDestroyed, Damage, DamageUpdate};
+use super::faction::{Faction, Factions};
+use super::formation::{Formation, Wingman};
+use super::gun::Gun;
+use super::missile::MissileRack;
+use super::mission::{MissionOptions, MissionRestart, RestartMission};
+use super::plane::PLANE_MODEL;
+use super::recording::RecorderUpdate;
+use super::sim_time::{SimClock, SimId, SimIds, SimTick, TIME_STEP};
+use super::spatial::{SpatialIndex, SpatialIndexUpdate};
+use super::terrain::HeightMap;
 use super::utils;
-use super::{Ally, Enemy, AI};
+use super::{Player, AI};
+use bfm::Bfm;
+use lod::{Lod, LodLevel};
+use orders::{Order, RadioMenu, WingmanOrder};
+use sensors::{CloudLayer, Contacts, Radar};
+use skill::{Skill, SkillProfiles};
+use state::{AIMode, AIState, HomeBase};
+use tactics::{Flight, FlightMember};
+use targeting::TargetWeights;
+use utility::UtilityBehaviour;
 
 const INITIAL_PLANE_ALTITUDE: f32 = 2000.0;
-const TARGET_VELOCITY: f32 = 50.0;
+/// distance from the middle of the map each faction spawns, patrols and bases at; the factions
+/// start out beyond radar range of each other and open the fight beyond visual range
+const SPAWN_DISTANCE: f32 = 9000.0;
+const PATROL_DISTANCE: f32 = 4500.0;
+const BASE_DISTANCE: f32 = 12000.0;
+/// aircraft per row when spawning
+const SPAWN_ROW_LENGTH: usize = 50;
+const FLIGHT_SIZE: usize = 4;
+/// allied AI that start on the player's wing
+const PLAYER_WINGMEN: usize = 2;
+/// aircraft handed to each task when the AI is run in parallel
+const BATCH_SIZE: usize = 32;
+/// radians per tick an AI can turn at corner speed, with a pilot who can take the g
+const MAX_TURN_RATE: f32 = 0.03;
+/// fraction of speed lost per tick turning at the maximum rate
+const TURN_BLEED: f32 = 0.01;
 
 pub struct AIPlugin;
 
 impl Plugin for AIPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(setup)
-            .add_system(ally_targeting_system)
-            .add_system(enemy_targeting_system)
-            .add_system(ally_ai_system)
-            .add_system(enemy_ai_system);
+        app.init_resource::<TargetWeights>()
+            .insert_resource(SkillProfiles::load())
+            .insert_resource(UtilityBehaviour::load())
+            .init_resource::<CloudLayer>()
+            .add_system(utility::behaviour_reload_system)
+            .init_resource::<RadioMenu>()
+            .add_system(orders::radio_system)
+            .add_startup_system(setup)
+            // after the player has been put back, so wingmen can be assigned to it
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                restart_system
+                    .label(MissionRestart::Aircraft)
+                    .after(MissionRestart::Player),
+            )
+            .add_system_set(ai_systems().with_run_criteria(SimTick))
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(SimTick)
+                    // with the wingmen's orders settled before any of them think
+                    .with_system(
+                        orders::radio_call_system
+                            .after(RecorderUpdate)
+                            .before(SpatialIndexUpdate),
+                    )
+                    .with_system(destroyed_system.after(DamageUpdate)),
+            );
     }
 }
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let f22_raptor = asset_server.load("models/f22-raptor/scene.gltf#Scene0");
-
-    for i in 0..10 {
-        commands
-            .spawn_bundle((
-                Transform::from_translation(Vec3::new(
-                    i as f32 * 100.0,
-                    INITIAL_PLANE_ALTITUDE,
-                    0.0,
-                )),
-                GlobalTransform::identity(),
-            ))
-            .insert(AI::default())
-            .insert(Enemy)
-            .with_children(|parent| {
-                // center of the plane is not at 0,0 so offset slightly
-                parent
-                    .spawn_bundle((
-                        Transform::from_translation(Vec3::new(0.0, -5.0, 0.0))
-                            .with_rotation(Quat::from_rotation_y(-std::f32::consts::FRAC_PI_2)),
-                        GlobalTransform::identity(),
-                    ))
-                    .with_children(|parent| {
-                        parent.spawn_scene(f22_raptor.clone());
-                    });
-            });
-    }
+/// Everything the AI does each tick, in order, after the spatial index has been rebuilt
+pub fn ai_systems() -> SystemSet {
+    SystemSet::new()
+        .with_system(lod::lod_system.after(SpatialIndexUpdate))
+        .with_system(
+            sensors::sensor_system
+                .after(SpatialIndexUpdate)
+                .after(lod::lod_system),
+        )
+        .with_system(
+            tactics::flight_system
+                .after(SpatialIndexUpdate)
+                .after(sensors::sensor_system),
+        )
+        .with_system(
+            targeting::targeting_system
+                .after(SpatialIndexUpdate)
+                .after(tactics::flight_system)
+                .after(lod::lod_system),
+        )
+        .with_system(
+            state::ai_state_system
+                .after(SpatialIndexUpdate)
+                .after(targeting::targeting_system),
+        )
+        .with_system(ai_system.after(state::ai_state_system))
+}
+
+/// A box of waypoints around `centre` for AI to patrol
+fn patrol_route(centre: Vec3) -> Vec<Vec3> {
+    vec![
+        centre + Vec3::new(-1500.0, 0.0, -1500.0),
+        centre + Vec3::new(1500.0, 0.0, -1500.0),
+        centre + Vec3::new(1500.0, 0.0, 1500.0),
+        centre + Vec3::new(-1500.0, 0.0, 1500.0),
+    ]
+}
+
+fn patrol_autopilot(centre: Vec3) -> Autopilot {
+    let mut autopilot = Autopilot::with_waypoints(patrol_route(centre));
+    autopilot.set_lateral(LateralMode::Navigation);
+    autopilot.set_vertical(VerticalMode::Altitude(centre.y));
+    autopilot
+}
+
+/// Offset of the `i`th aircraft in a faction's spawn block, rows stacking away from the middle
+fn spawn_offset(i: usize, direction: Vec3) -> Vec3 {
+    let across = direction.cross(Vec3::Y);
+    across * (i % SPAWN_ROW_LENGTH) as f32 * 100.0
+        + direction * (i / SPAWN_ROW_LENGTH) as f32 * 100.0
+}
+
+fn spawn_ai(
+    commands: &mut Commands,
+    ids: &mut SimIds,
+    scene: Option<&Handle<Scene>>,
+    translation: Vec3,
+) -> Entity {
+    let id = ids.allocate();
+    let mut aircraft = commands.spawn_bundle((
+        Transform::from_translation(translation),
+        GlobalTransform::identity(),
+    ));
+    aircraft
+        .insert(id)
+        .insert(AI::default())
+        .insert(AIState::default())
+        .insert(Bfm::default())
+        .insert(Lod::new(id.0 as u64))
+        .insert(Radar::FIGHTER)
+        .insert(Gun::default())
+        .insert(Damage::default())
+        .insert(MissileRack::default())
+        .insert(Contacts::default());
 
-    for i in 0..10 {
-        commands
-            .spawn_bundle((
-                Transform::from_translation(Vec3::new(
-                    i as f32 * 100.0,
-                    INITIAL_PLANE_ALTITUDE,
-                    1000.0,
-                )),
-                GlobalTransform::identity(),
-            ))
-            .insert(AI::default())
-            .insert(Ally)
-            .with_children(|parent| {
-                // center of the plane is not at 0,0 so offset slightly
-                parent
-                    .spawn_bundle((
-                        Transform::from_translation(Vec3::new(0.0, -5.0, 0.0))
-                            .with_rotation(Quat::from_rotation_y(-std::f32::consts::FRAC_PI_2)),
-                        GlobalTransform::identity(),
-                    ))
-                    .with_children(|parent| {
-                        parent.spawn_scene(f22_raptor.clone());
-                    });
-            });
+    if let Some(scene) = scene {
+        aircraft.with_children(|parent| {
+            // center of the plane is not at 0,0 so offset slightly
+            parent
+                .spawn_bundle((
+                    Transform::from_translation(Vec3::new(0.0, -5.0, 0.0))
+                        .with_rotation(Quat::from_rotation_y(-std::f32::consts::FRAC_PI_2)),
+                    GlobalTransform::identity(),
+                ))
+                .with_children(|parent| {
+                    parent.spawn_scene(scene.clone());
+                });
+        });
     }
+
+    aircraft.id()
 }
 
-fn ally_targeting_system(
-    mut allies: Query<(&Transform, &mut AI), (With<Ally>, Without<Enemy>)>,
-    enemies: Query<(Entity, &Transform), (With<Enemy>, Without<Ally>)>,
+/// Groups aircraft into flights of consecutive aircraft, the first of each leading the rest,
+/// with every member of a flight sharing its skill profile
+fn spawn_flights(
+    commands: &mut Commands,
+    ids: &mut SimIds,
+    faction: Faction,
+    faction_name: &str,
+    aircraft: &[Entity],
+    profiles: &SkillProfiles,
 ) {
-    for (transform, mut ai) in allies.iter_mut() {
-        if ai.target == None {
-            let mut targets = Vec::new();
+    for (i, members) in aircraft.chunks(FLIGHT_SIZE).enumerate() {
+        let flight = commands
+            .spawn()
+            .insert(Flight::new(faction, members.to_vec()))
+            .insert(ids.allocate())
+            .id();
+        let lead = members[0];
+        let skill = profiles.flight(faction_name, i);
 
-            for (target, target_transform) in enemies.iter() {
-                targets.push((target, target_transform));
+        for (slot, &member) in members.iter().enumerate() {
+            commands
+                .entity(member)
+                .insert(FlightMember::new(flight, slot))
+                .insert(skill.clone());
+            if slot == 0 {
+                commands.entity(member).insert(Formation::default());
+            } else {
+                commands.entity(member).insert(Wingman::new(lead, slot));
             }
-
-            ai.target = utils::find_closest_target(targets, transform.translation)
         }
     }
 }
 
-fn enemy_targeting_system(
-    allies: Query<(Entity, &Transform), (With<Ally>, Without<Enemy>)>,
-    mut enemies: Query<(&Transform, &mut AI), (With<Enemy>, Without<Ally>)>,
+/// The AI aircraft's model, loaded once for every time the mission starts
+struct AircraftScene(Handle<Scene>);
+
+fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(AircraftScene(asset_server.load(PLANE_MODEL)));
+}
+
+/// Clears away the AI aircraft and their flights, and spawns them again where they start
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn restart_system(
+    mut commands: Commands,
+    mut events: EventReader<RestartMission>,
+    mut ids: ResMut<SimIds>,
+    scene: Res<AircraftScene>,
+    profiles: Res<SkillProfiles>,
+    factions: Res<Factions>,
+    options: Res<MissionOptions>,
+    player_query: Query<Entity, With<Player>>,
+    query: Query<Entity, Or<(With<AI>, With<Flight>)>>,
 ) {
-    for (transform, mut ai) in enemies.iter_mut() {
-        if ai.target == None {
-            let mut targets = Vec::new();
+    if events.iter().count() == 0 {
+        return;
+    }
 
-            for (target, target_transform) in allies.iter() {
-                targets.push((target, target_transform));
-            }
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    spawn_factions(
+        &mut commands,
+        &mut ids,
+        &factions,
+        &profiles,
+        options.stress,
+        Some(player_query.single()),
+        Some(&scene.0),
+    );
+}
+
+/// Spawns each faction's aircraft, or `total` split between them if given. Without a `player`
+/// nobody flies on its wing, and without a `scene` the aircraft have no model.
+pub fn spawn_factions(
+    commands: &mut Commands,
+    ids: &mut SimIds,
+    factions: &Factions,
+    profiles: &SkillProfiles,
+    total: Option<usize>,
+    player: Option<Entity>,
+    scene: Option<&Handle<Scene>>,
+) {
+    // factions spread evenly around the middle of the map
+    let count = factions.factions.len();
+    for (i, config) in factions.factions.iter().enumerate() {
+        let faction = Faction(i);
+        let angle = i as f32 * std::f32::consts::TAU / count as f32;
+        let direction = Vec3::new(angle.sin(), 0.0, angle.cos());
+        let altitude = Vec3::new(0.0, INITIAL_PLANE_ALTITUDE, 0.0);
+        let patrol = altitude + direction * PATROL_DISTANCE;
+        let base = altitude + direction * BASE_DISTANCE;
+
+        let aircraft = total.map_or(config.aircraft, |total| total / count);
+        let mut flights = Vec::new();
+        for j in 0..aircraft {
+            let translation = altitude + direction * SPAWN_DISTANCE + spawn_offset(j, direction);
+            let entity = spawn_ai(commands, ids, scene, translation);
+            commands
+                .entity(entity)
+                .insert(faction)
+                .insert(HomeBase(base))
+                .insert(patrol_autopilot(patrol));
 
-            ai.target = utils::find_closest_target(targets, transform.translation)
+            // the player's faction puts its first pair on the player's wing,
+            // the rest form their own flights
+            match player {
+                Some(player) if faction == Faction::PLAYER && j < PLAYER_WINGMEN => {
+                    commands
+                        .entity(entity)
+                        .insert(Wingman::new(player, j + 1))
+                        .insert(WingmanOrder(Order::EngageBandits))
+                        .insert(profiles.get(&profiles.wingmen));
+                }
+                _ => flights.push(entity),
+            }
         }
+        spawn_flights(commands, ids, faction, &config.name, &flights, profiles);
     }
 }
 
-fn ally_ai_system(
-    mut query: Query<(&mut Transform, &mut AI), (With<Ally>, Without<Enemy>)>,
-    targets: Query<&Transform, (With<Enemy>, Without<Ally>)>,
+/// Flies each AI: the autopilot or formation on patrol, BFM in a fight, and avoidance over all of it.
+/// Between decisions an aircraft keeps flying towards where it last decided to go.
+#[allow(clippy::type_complexity)]
+pub fn ai_system(
+    mut query: Query<(
+        (Entity, &SimId),
+        &mut Transform,
+        &mut AI,
+        &Damage,
+        &AIState,
+        &mut Bfm,
+        &mut Lod,
+        &mut Gun,
+        &Contacts,
+        &Skill,
+        &HomeBase,
+        Option<&mut Autopilot>,
+        Option<&mut Wingman>,
+        Option<&WingmanOrder>,
+        Option<&FlightMember>,
+    )>,
+    formations: Query<&Formation>,
+    pool: Res<ComputeTaskPool>,
+    index: Res<SpatialIndex>,
+    height_map: Res<HeightMap>,
+    clock: Res<SimClock>,
+    behaviour: Res<UtilityBehaviour>,
 ) {
-    for (transform, mut ai) in query.iter_mut() {
+    query.par_for_each_mut(&pool, BATCH_SIZE, |item| {
+        let (
+            (entity, id),
+            transform,
+            mut ai,
+            damage,
+            state,
+            mut bfm,
+            mut lod,
+            mut gun,
+            contacts,
+            skill,
+            home,
+            autopilot,
+            wingman,
+            order,
+            member,
+        ) = item;
+        let ground = height_map.height(transform.translation);
+        // a shot-up airframe can't pull as hard
+        let turn_rate = MAX_TURN_RATE * skill.g_tolerance * damage.roll().min(damage.pitch_yaw());
+
+        if let (false, Some((point, speed))) = (lod.due(clock.tick), lod.steer) {
+            fly(transform, ai, lod.level, point, speed, turn_rate, ground);
+            return;
+        }
+        let elapsed = lod.elapsed(clock.tick);
+
         let mut target_vec = transform.forward();
+        let mut target_speed = skill.cruise_speed;
 
+        // destroyed targets are dropped as they go down, this catches anything despawned outright
         if let Some(target) = ai.target {
-            if let Ok(transform) = targets.get(target) {
-                target_vec = transform.translation.clone()
-            } else {
-                error!("Target not found!");
+            if index.get(target).is_none() {
                 ai.target = None;
             }
         }
+        // the target's wherever we last knew it to be, which may not be where it is
+        let target = ai.target.and_then(|target| contacts.get(target));
 
-        ai_follow_target(transform, ai, target_vec)
-    }
+        if let Some(target) = target {
+            target_vec = bfm::lead_point(transform.translation, ai.velocity, target);
+        }
+
+        if let Some(mut autopilot) = autopilot {
+            if target.is_none() || state.mode == AIMode::Patrol {
+                if let Some(point) = autopilot.guidance_point(&transform, ai.velocity) {
+                    target_vec = point;
+                }
+            }
+            target_speed = autopilot.autothrottle.unwrap_or(skill.cruise_speed);
+        }
+
+        // wingmen hold their slot rather than flying the route themselves,
+        // and rejoin once they're back on patrol after a fight, unless told to hold
+        let holding = matches!(order, Some(WingmanOrder(Order::HoldPosition(_))));
+        if let (AIMode::Patrol, Some(mut wingman), false) = (state.mode, wingman, holding) {
+            let lead = index.get(wingman.lead);
+            if let (Some(lead), Ok(formation)) = (lead, formations.get(wingman.lead)) {
+                let (point, speed) = wingman.station_keeping(
+                    lead,
+                    formation,
+                    transform.translation,
+                    TIME_STEP * elapsed as f32,
+                );
+                target_vec = point;
+                target_speed = speed;
+            }
+        }
+
+        // the flight's tactic shapes the approach, up until the merge
+        if let (AIMode::Patrol | AIMode::Intercept, Some(member)) = (state.mode, member) {
+            if let Some((point, speed)) = member.steer {
+                target_vec = point;
+                target_speed = skill.cruise_speed * speed;
+            }
+        }
+
+        let threat = state
+            .threat
+            .and_then(|threat| contacts.track(threat))
+            .map(|track| &track.entry);
+        if let Some((point, speed)) = state::steering(
+            state,
+            &transform,
+            home,
+            threat.map(|threat| threat.position),
+            skill.cruise_speed,
+        ) {
+            target_vec = point;
+            target_speed = speed;
+        }
+
+        // dogfight the target, or fight off whatever is on our tail,
+        // unless too far away for anyone to see the difference
+        let opponent = match (lod.level, state.mode) {
+            (LodLevel::Distant, _) => None,
+            (_, AIMode::Engage) => target,
+            (_, AIMode::Evade) => threat,
+            _ => None,
+        };
+        match opponent {
+            Some(opponent) => {
+                let (point, speed) = bfm.steer(
+                    &transform,
+                    ai.velocity,
+                    opponent,
+                    &height_map,
+                    skill,
+                    &behaviour.manoeuvres,
+                    elapsed,
+                );
+                target_vec = point + aim_error(*id, clock.tick, skill, &transform, point);
+                target_speed = speed;
+                gun.trigger = !order.is_some_and(|order| order.0.holds_fire())
+                    && bfm::gun_solution(&transform, ai.velocity, opponent);
+            }
+            None => {
+                *bfm = Bfm::default();
+                gun.trigger = false;
+            }
+        }
+
+        let avoidance = match lod.level {
+            LodLevel::Distant => avoidance::terrain_escape(&transform, ai.velocity, &height_map),
+            _ => avoidance::avoidance_point(entity, &transform, ai.velocity, &index, &height_map),
+        };
+        if let Some(point) = avoidance {
+            target_vec = point;
+        }
+
+        // with the engine hit there's only so much speed to be had
+        target_speed *= 0.4 + 0.6 * damage.thrust();
+
+        lod.thought(clock.tick, (target_vec, target_speed));
+        fly(
+            transform,
+            ai,
+            lod.level,
+            target_vec,
+            target_speed,
+            turn_rate,
+            ground,
+        );
+    });
 }
 
-fn enemy_ai_system(
-    mut query: Query<(&mut Transform, &mut AI), (With<Enemy>, Without<Ally>)>,
-    targets: Query<&Transform, (With<Ally>, Without<Enemy>)>,
+/// Lets go of every aircraft shot down this tick: targets, threats, tracks, orders and flights
+#[allow(clippy::type_complexity)]
+pub fn destroyed_system(
+    mut commands: Commands,
+    mut events: EventReader<AircraftDestroyed>,
+    mut query: Query<(
+        &mut AI,
+        &mut AIState,
+        &mut Contacts,
+        Option<&mut WingmanOrder>,
+        Option<&mut FlightMember>,
+    )>,
+    mut flights: Query<&mut Flight>,
+    mut wingmen: Query<&mut Wingman>,
+    formations: Query<&Formation>,
 ) {
-    for (transform, mut ai) in query.iter_mut() {
-        let mut target_vec = transform.forward();
+    for event in events.iter() {
+        let destroyed = event.entity;
 
-        if let Some(target) = ai.target {
-            if let Ok(transform) = targets.get(target) {
-                target_vec = transform.translation.clone() + transform.forward() * -100.0
-            } else {
-                error!("Target not found!");
+        for (mut ai, mut state, mut contacts, order, member) in query.iter_mut() {
+            if ai.target == Some(destroyed) {
                 ai.target = None;
             }
+            if state.threat == Some(destroyed) {
+                state.threat = None;
+            }
+            contacts.forget(destroyed);
+            if let Some(mut order) = order {
+                if matches!(order.0, Order::AttackTarget(target) | Order::Cover(target) if target == destroyed)
+                {
+                    order.0 = Order::EngageBandits;
+                }
+            }
+            if let Some(mut member) = member {
+                if member.assigned == Some(destroyed) {
+                    member.assigned = None;
+                }
+            }
         }
 
-        ai_follow_target(transform, ai, target_vec)
+        // the next member along takes the lead if it was the lead that went down, keeping the
+        // formation it was flying, and the rest close up on it
+        for mut flight in flights.iter_mut() {
+            let was_lead = flight.members.first() == Some(&destroyed);
+            flight.members.retain(|member| *member != destroyed);
+            let lead = match flight.members.first() {
+                Some(&lead) if was_lead => lead,
+                _ => continue,
+            };
+
+            let formation = formations.get(destroyed).map_or_else(
+                |_| Formation::default(),
+                |formation| Formation {
+                    shape: formation.shape,
+                    spacing: formation.spacing,
+                },
+            );
+            commands.entity(lead).remove::<Wingman>().insert(formation);
+            for (slot, member) in flight.members.iter().enumerate().skip(1) {
+                if let Ok(mut wingman) = wingmen.get_mut(*member) {
+                    wingman.lead = lead;
+                    wingman.slot = slot;
+                }
+            }
+        }
     }
 }
 
-fn ai_follow_target(mut transform: Mut<Transform>, mut ai: Mut<AI>, target_vec: Vec3) {
+fn fly(
+    transform: Mut<Transform>,
+    ai: Mut<AI>,
+    level: LodLevel,
+    target_vec: Vec3,
+    target_speed: f32,
+    turn_rate: f32,
+    ground: f32,
+) {
+    match level {
+        LodLevel::Full | LodLevel::Reduced => {
+            ai_follow_target(transform, ai, target_vec, target_speed, turn_rate, ground)
+        }
+        LodLevel::Distant => {
+            ai_fly_kinematic(transform, ai, target_vec, target_speed, turn_rate, ground)
+        }
+    }
+}
+
+/// A slowly wandering offset from the aim point, scaled by range and the pilot's skill.
+/// It's derived from the tick rather than drawn from the RNG so that it doesn't depend on
+/// the order aircraft are updated in.
+fn aim_error(id: SimId, tick: u64, skill: &Skill, transform: &Transform, point: Vec3) -> Vec3 {
+    let phase = id.0 as f32 * 1.7;
+    let t = tick as f32 * TIME_STEP;
+    let wander = Vec3::new(
+        (t * 0.9 + phase).sin(),
+        (t * 1.3 + phase * 2.0).sin(),
+        (t * 0.7 + phase * 3.0).cos(),
+    );
+    wander * skill.aim_error * transform.translation.distance(point)
+}
+
+fn ai_follow_target(
+    mut transform: Mut<Transform>,
+    mut ai: Mut<AI>,
+    target_vec: Vec3,
+    target_speed: f32,
+    turn_rate: f32,
+    ground: f32,
+) {
     let forwards = transform.forward();
 
     let axis_deviance = ai
@@ -157,13 +592,26 @@ fn ai_follow_target(mut transform: Mut<Transform>, mut ai: Mut<AI>, target_vec:
         .dot(forwards.normalize_or_zero())
         / forwards.length();
 
+    // turn no faster than the pilot can stand, which is fastest around corner speed
+    let speed = ai.velocity.length();
+    let max_turn = turn_rate
+        * utils::clamp(
+            1.0 - (speed - bfm::CORNER_SPEED).abs() / bfm::CORNER_SPEED,
+            0.4,
+            1.0,
+        );
+    let current = transform.rotation;
     let angle = Vec3::Z;
     transform.look_at(target_vec, angle);
+    let turn = current.angle_between(transform.rotation);
+    if turn > max_turn {
+        transform.rotation = current.slerp(transform.rotation, max_turn / turn);
+    }
 
-    // thrust
+    // thrust, less whatever the turn bled off
     let normal = ai.velocity.normalize();
-    let speed = ai.velocity.length();
-    ai.velocity = normal * utils::lerp(speed, TARGET_VELOCITY, 0.1);
+    let bleed = 1.0 - TURN_BLEED * turn.min(max_turn) / MAX_TURN_RATE;
+    ai.velocity = normal * utils::lerp(speed, target_speed, 0.1) * bleed;
 
     // nudge velocity vector towards the forwards vector
     ai.velocity = ai.velocity.lerp(
@@ -173,7 +621,38 @@ fn ai_follow_target(mut transform: Mut<Transform>, mut ai: Mut<AI>, target_vec:
 
     transform.translation += ai.velocity;
 
-    if transform.translation.y < 0.0 {
-        transform.translation.y = 0.0;
+    if transform.translation.y < ground {
+        transform.translation.y = ground;
+    }
+}
+
+/// The cheap flight model for distant aircraft: the velocity turns straight towards the point
+/// and the aircraft is pointed along it, with no bleed or sideslip
+fn ai_fly_kinematic(
+    mut transform: Mut<Transform>,
+    mut ai: Mut<AI>,
+    target_vec: Vec3,
+    target_speed: f32,
+    turn_rate: f32,
+    ground: f32,
+) {
+    let speed = utils::lerp(ai.velocity.length(), target_speed, 0.1);
+    let mut heading = ai.velocity.normalize_or_zero();
+    let wanted = (target_vec - transform.translation).normalize_or_zero();
+    if heading == Vec3::ZERO {
+        heading = transform.forward();
+    }
+    if wanted != Vec3::ZERO {
+        let (axis, angle) = Quat::from_rotation_arc(heading, wanted).to_axis_angle();
+        heading = Quat::from_axis_angle(axis, angle.min(turn_rate)) * heading;
+    }
+
+    ai.velocity = heading * speed;
+    transform.translation += ai.velocity;
+    if transform.translation.y < ground {
+        transform.translation.y = ground;
     }
+
+    let ahead = transform.translation + heading;
+    transform.look_at(ahead, Vec3::Y);
 }
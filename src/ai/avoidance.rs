@@ -0,0 +1,89 @@
+use bevy::prelude::*;
+
+use crate::spatial::{Domain, SpatialIndex};
+use crate::terrain::HeightMap;
+
+/// ticks ahead that collisions with other aircraft are predicted
+const AIRCRAFT_LOOK_AHEAD: f32 = 90.0;
+/// predicted misses closer than this count as collisions, inside the tightest formation spacing
+const SEPARATION: f32 = 40.0;
+/// furthest off other aircraft are checked for collisions, however fast the closure
+const MAX_SEARCH_RADIUS: f32 = 400.0;
+/// ticks ahead along the flight path that the ground is probed
+const TERRAIN_PROBES: [f32; 4] = [15.0, 30.0, 60.0, 120.0];
+/// height to keep above the ground and buildings
+const TERRAIN_CLEARANCE: f32 = 150.0;
+/// how far ahead the escape point is placed
+const STEER_DISTANCE: f32 = 1000.0;
+
+/// A point to steer for instead of the pursuit point, if something is about to be hit.
+/// Terrain takes priority, since pulling up also clears most aircraft conflicts.
+pub fn avoidance_point(
+    entity: Entity,
+    transform: &Transform,
+    velocity: Vec3,
+    index: &SpatialIndex,
+    height_map: &HeightMap,
+) -> Option<Vec3> {
+    terrain_escape(transform, velocity, height_map)
+        .or_else(|| aircraft_escape(entity, transform, velocity, index))
+}
+
+pub fn terrain_escape(
+    transform: &Transform,
+    velocity: Vec3,
+    height_map: &HeightMap,
+) -> Option<Vec3> {
+    let position = transform.translation;
+
+    let conflict = std::iter::once(0.0)
+        .chain(TERRAIN_PROBES)
+        .map(|ticks| position + velocity * ticks)
+        .any(|probe| probe.y < height_map.height(probe) + TERRAIN_CLEARANCE);
+    if !conflict {
+        return None;
+    }
+
+    // keep heading, but climb hard
+    let horizontal = Vec3::new(velocity.x, 0.0, velocity.z).normalize_or_zero();
+    Some(position + (horizontal + Vec3::Y).normalize() * STEER_DISTANCE)
+}
+
+/// Steers away from the aircraft whose closest approach comes soonest, if it comes too close
+fn aircraft_escape(
+    entity: Entity,
+    transform: &Transform,
+    velocity: Vec3,
+    index: &SpatialIndex,
+) -> Option<Vec3> {
+    let position = transform.translation;
+    let search_radius =
+        (velocity.length() * 2.0 * AIRCRAFT_LOOK_AHEAD + SEPARATION).min(MAX_SEARCH_RADIUS);
+
+    let (_, miss) = index
+        .within_radius(position, search_radius)
+        .filter(|other| other.entity != entity && other.domain == Domain::Air)
+        .filter_map(|other| {
+            let offset = other.position - position;
+            let closing = other.velocity - velocity;
+            let time = if closing.length_squared() > 0.0 {
+                (-offset.dot(closing) / closing.length_squared()).clamp(0.0, AIRCRAFT_LOOK_AHEAD)
+            } else {
+                0.0
+            };
+            let miss = offset + closing * time;
+            (miss.length() < SEPARATION).then_some((time, miss))
+        })
+        .min_by(|(a, _), (b, _)| a.total_cmp(b))?;
+
+    // head-on or dead astern there's no side to pick, so both break right
+    let away = (-miss).normalize_or_zero();
+    let away = if away == Vec3::ZERO {
+        transform.local_x()
+    } else {
+        away + transform.local_x() * 0.25
+    };
+
+    let forward = velocity.normalize_or_zero();
+    Some(position + (forward + away * 2.0).normalize_or_zero() * STEER_DISTANCE)
+}
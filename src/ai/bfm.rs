@@ -0,0 +1,281 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::skill::Skill;
+use super::utility::{ManoeuvreFactor, Utility};
+use crate::gun::MUZZLE_VELOCITY;
+use crate::spatial::SpatialEntry;
+use crate::terrain::HeightMap;
+use crate::utils;
+
+/// speed, in units per tick, at which an aircraft turns fastest
+pub const CORNER_SPEED: f32 = 45.0;
+/// inside this range the target is close enough to shoot
+const GUN_RANGE: f32 = 800.0;
+/// radians between the nose and where the rounds need to go that the AI opens fire inside
+const GUN_CONE: f32 = 0.03;
+/// ticks of flight time that lead pursuit leads the target by at most
+const MAX_LEAD_TICKS: f32 = 60.0;
+const LAG_DISTANCE: f32 = 300.0;
+/// ticks a manoeuvre is flown before it's reconsidered
+const MIN_MANOEUVRE_TICKS: u32 = 60;
+/// ticks between reversals in the scissors
+const SCISSORS_PERIOD: u32 = 90;
+/// radians per tick the barrel roll's spiral advances
+const BARREL_ROLL_RATE: f32 = 0.05;
+/// how far ahead manoeuvre steering points are placed
+const STEER_DISTANCE: f32 = 1000.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+pub enum Manoeuvre {
+    /// nose ahead of the target, to close and to take a gun shot
+    LeadPursuit,
+    /// nose on the target
+    PurePursuit,
+    /// nose behind the target, to close without overshooting
+    LagPursuit,
+    /// pull up out of the target's plane to bleed closure, then come back down behind it
+    HighYoYo,
+    /// drop below the target's plane to cut across its turn and gain closure
+    LowYoYo,
+    /// half loop up and over, reversing direction and trading speed for height
+    Immelmann,
+    /// half roll and pull through downwards, reversing direction and trading height for speed
+    SplitS,
+    /// maximum rate turn across the attacker's line of sight
+    BreakTurn,
+    /// repeated turns into the attacker, slowing down to make it overshoot
+    Scissors,
+    /// spiral around the flight path, slowing down to make the attacker overshoot
+    BarrelRollDefence,
+}
+
+impl Manoeuvre {
+    /// what a pilot who doesn't know this manoeuvre flies instead
+    pub fn simpler(self) -> Option<Manoeuvre> {
+        match self {
+            Manoeuvre::PurePursuit => None,
+            Manoeuvre::LeadPursuit | Manoeuvre::LagPursuit | Manoeuvre::BreakTurn => {
+                Some(Manoeuvre::PurePursuit)
+            }
+            Manoeuvre::HighYoYo => Some(Manoeuvre::LagPursuit),
+            Manoeuvre::LowYoYo => Some(Manoeuvre::LeadPursuit),
+            Manoeuvre::Immelmann | Manoeuvre::SplitS => Some(Manoeuvre::LagPursuit),
+            Manoeuvre::Scissors | Manoeuvre::BarrelRollDefence => Some(Manoeuvre::BreakTurn),
+        }
+    }
+}
+
+/// The manoeuvre an AI is flying and what it needs to remember while flying it
+#[derive(Component)]
+pub struct Bfm {
+    pub manoeuvre: Manoeuvre,
+    ticks: u32,
+    /// direction of flight when the manoeuvre started, for the reversals
+    entry_forward: Vec3,
+    /// which way to turn for the break and the scissors, 1 for right
+    turn: f32,
+}
+
+impl Default for Bfm {
+    fn default() -> Self {
+        Bfm {
+            manoeuvre: Manoeuvre::PurePursuit,
+            // so the first tick of a fight picks a manoeuvre straight away
+            ticks: MIN_MANOEUVRE_TICKS,
+            entry_forward: Vec3::ZERO,
+            turn: 1.0,
+        }
+    }
+}
+
+/// Relative geometry of two aircraft in a fight
+pub struct Geometry {
+    pub range: f32,
+    /// angle between our nose and the line of sight to the opponent
+    pub antenna_train: f32,
+    /// angle between the opponent's nose and the line of sight from it to us, 0 when it's
+    /// pointing at us
+    pub threat_angle: f32,
+    /// angle between our heading and the opponent's
+    pub angle_off: f32,
+    /// rate the range is closing at, in units per tick
+    pub closure: f32,
+    /// our specific energy less the opponent's, as a fraction of ours
+    pub energy_advantage: f32,
+    pub height_above_ground: f32,
+}
+
+/// height plus the height our speed could be traded for
+fn specific_energy(position: Vec3, velocity: Vec3) -> f32 {
+    let speed = velocity.length() * 60.0;
+    position.y + speed * speed / (2.0 * 9.81)
+}
+
+fn angle_between(a: Vec3, b: Vec3) -> f32 {
+    utils::clamp(a.normalize_or_zero().dot(b.normalize_or_zero()), -1.0, 1.0).acos()
+}
+
+impl Geometry {
+    pub fn new(
+        transform: &Transform,
+        velocity: Vec3,
+        opponent: &SpatialEntry,
+        height_map: &HeightMap,
+    ) -> Self {
+        let position = transform.translation;
+        let offset = opponent.position - position;
+        let line_of_sight = offset.normalize_or_zero();
+
+        let own_energy = specific_energy(position, velocity);
+        let energy_advantage = (own_energy - specific_energy(opponent.position, opponent.velocity))
+            / own_energy.max(1.0);
+
+        Geometry {
+            range: offset.length(),
+            antenna_train: angle_between(transform.forward(), line_of_sight),
+            threat_angle: angle_between(opponent.forward, -line_of_sight),
+            angle_off: angle_between(transform.forward(), opponent.forward),
+            closure: (velocity - opponent.velocity).dot(line_of_sight),
+            energy_advantage,
+            height_above_ground: position.y - height_map.height(position),
+        }
+    }
+
+    pub fn factor(&self, factor: ManoeuvreFactor) -> f32 {
+        match factor {
+            ManoeuvreFactor::Range => self.range,
+            ManoeuvreFactor::AntennaTrain => self.antenna_train,
+            ManoeuvreFactor::ThreatAngle => self.threat_angle,
+            ManoeuvreFactor::AngleOff => self.angle_off,
+            ManoeuvreFactor::Closure => self.closure,
+            ManoeuvreFactor::EnergyAdvantage => self.energy_advantage,
+            ManoeuvreFactor::HeightAboveGround => self.height_above_ground,
+        }
+    }
+}
+
+/// Where the target will be by the time we get there
+pub fn lead_point(position: Vec3, velocity: Vec3, target: &SpatialEntry) -> Vec3 {
+    let range = target.position.distance(position);
+    let lead_ticks = utils::clamp(range / velocity.length().max(1.0), 0.0, MAX_LEAD_TICKS);
+    target.position + target.velocity * lead_ticks
+}
+
+/// Whether the opponent is in gun range and the nose is on where the rounds will meet it
+pub fn gun_solution(transform: &Transform, velocity: Vec3, opponent: &SpatialEntry) -> bool {
+    let offset = opponent.position - transform.translation;
+    let range = offset.length();
+    if range > GUN_RANGE {
+        return false;
+    }
+
+    // the rounds carry our own velocity, so only the opponent's motion relative to ours needs leading
+    let time_of_flight = range / (MUZZLE_VELOCITY + velocity.length());
+    let aim = offset + (opponent.velocity - velocity) * time_of_flight;
+    transform.forward().dot(aim.normalize_or_zero()) > GUN_CONE.cos()
+}
+
+impl Bfm {
+    fn start(&mut self, manoeuvre: Manoeuvre, transform: &Transform, opponent: &SpatialEntry) {
+        self.manoeuvre = manoeuvre;
+        self.ticks = 0;
+        self.entry_forward = transform.forward();
+
+        // turn towards the side the opponent is on
+        let offset = opponent.position - transform.translation;
+        self.turn = if offset.dot(transform.local_x()) >= 0.0 {
+            1.0
+        } else {
+            -1.0
+        };
+    }
+
+    /// whether the manoeuvre has done its job or should be reconsidered
+    fn finished(&self, transform: &Transform) -> bool {
+        match self.manoeuvre {
+            // the reversals run until we're pointing back the way we came, or give up trying
+            Manoeuvre::Immelmann | Manoeuvre::SplitS => {
+                transform.forward().dot(self.entry_forward) < -0.5
+                    || self.ticks >= MIN_MANOEUVRE_TICKS * 6
+            }
+            _ => self.ticks >= MIN_MANOEUVRE_TICKS,
+        }
+    }
+
+    /// Steering point and speed against `opponent`, `elapsed` ticks after the last
+    #[allow(clippy::too_many_arguments)]
+    pub fn steer(
+        &mut self,
+        transform: &Transform,
+        velocity: Vec3,
+        opponent: &SpatialEntry,
+        height_map: &HeightMap,
+        skill: &Skill,
+        manoeuvres: &Utility<Manoeuvre, ManoeuvreFactor>,
+        elapsed: u32,
+    ) -> (Vec3, f32) {
+        let geometry = Geometry::new(transform, velocity, opponent, height_map);
+        let cruise_speed = skill.cruise_speed;
+
+        self.ticks += elapsed;
+        if self.finished(transform) {
+            let manoeuvre = manoeuvres.choose(self.manoeuvre, skill.aggression, |factor| {
+                geometry.factor(factor)
+            });
+            let manoeuvre = skill.repertoire(manoeuvre);
+            // a finished reversal has to be started afresh, anything else just carries on
+            if manoeuvre != self.manoeuvre
+                || matches!(manoeuvre, Manoeuvre::Immelmann | Manoeuvre::SplitS)
+            {
+                self.start(manoeuvre, transform, opponent);
+            }
+        }
+
+        let position = transform.translation;
+        let forward = transform.forward();
+        let lead_point = lead_point(position, velocity, opponent);
+        let lag_point = opponent.position - opponent.forward * LAG_DISTANCE;
+        let right = transform.local_x();
+        let up = Vec3::Y;
+
+        match self.manoeuvre {
+            Manoeuvre::LeadPursuit => (lead_point, cruise_speed),
+            Manoeuvre::PurePursuit => (opponent.position, cruise_speed),
+            Manoeuvre::LagPursuit => (lag_point, cruise_speed),
+            Manoeuvre::HighYoYo => (lag_point + up * geometry.range * 0.5, cruise_speed * 0.8),
+            Manoeuvre::LowYoYo => (lead_point - up * geometry.range * 0.3, cruise_speed * 1.2),
+            Manoeuvre::Immelmann => (
+                position + up * STEER_DISTANCE - self.entry_forward * STEER_DISTANCE * 0.3,
+                cruise_speed,
+            ),
+            Manoeuvre::SplitS => (
+                position - up * STEER_DISTANCE - self.entry_forward * STEER_DISTANCE * 0.3,
+                cruise_speed * 1.2,
+            ),
+            Manoeuvre::BreakTurn => (
+                position + (right * self.turn + forward * 0.2) * STEER_DISTANCE,
+                CORNER_SPEED,
+            ),
+            Manoeuvre::Scissors => {
+                let turn = if (self.ticks / SCISSORS_PERIOD).is_multiple_of(2) {
+                    self.turn
+                } else {
+                    -self.turn
+                };
+                (
+                    position + (right * turn + forward * 0.5) * STEER_DISTANCE,
+                    cruise_speed * 0.6,
+                )
+            }
+            Manoeuvre::BarrelRollDefence => {
+                let angle = self.ticks as f32 * BARREL_ROLL_RATE * self.turn;
+                let spiral = (right * angle.cos() + up * angle.sin()) * STEER_DISTANCE * 0.4;
+                (
+                    position + forward * STEER_DISTANCE + spiral,
+                    cruise_speed * 0.7,
+                )
+            }
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use bevy::{prelude::*, tasks::ComputeTaskPool};
+
+use super::BATCH_SIZE;
+use crate::sim_time::SimClock;
+use crate::AI;
+
+/// aircraft closer than this to a focus think every tick
+const FULL_RANGE: f32 = 5000.0;
+/// aircraft closer than this think every few ticks, further ones fly a cheap kinematic model
+const REDUCED_RANGE: f32 = 15000.0;
+/// an aircraft has to be this much further out than a level's range before it's demoted,
+/// so one sat on the boundary doesn't flip back and forth
+const HYSTERESIS: f32 = 1000.0;
+
+/// Something the simulation is detailed around, usually the player
+#[derive(Component)]
+pub struct LodFocus;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum LodLevel {
+    /// full AI and flight model every tick
+    Full,
+    /// AI every few ticks, full flight model every tick
+    Reduced,
+    /// AI rarely, kinematic flight model, no BFM and no avoidance of other aircraft
+    Distant,
+}
+
+impl LodLevel {
+    fn at(distance: f32) -> Self {
+        if distance < FULL_RANGE {
+            LodLevel::Full
+        } else if distance < REDUCED_RANGE {
+            LodLevel::Reduced
+        } else {
+            LodLevel::Distant
+        }
+    }
+
+    /// ticks between AI decisions
+    pub fn interval(self) -> u64 {
+        match self {
+            LodLevel::Full => 1,
+            LodLevel::Reduced => 4,
+            LodLevel::Distant => 30,
+        }
+    }
+}
+
+/// How much detail an AI aircraft is simulated in, and when it next thinks
+#[derive(Component)]
+pub struct Lod {
+    pub level: LodLevel,
+    /// offsets this aircraft's decisions from others at the same level,
+    /// so they don't all think on the same tick
+    phase: u64,
+    last_think: u64,
+    next_think: u64,
+    /// steering point and speed from the last decision, flown until the next
+    pub steer: Option<(Vec3, f32)>,
+}
+
+impl Lod {
+    pub fn new(phase: u64) -> Self {
+        Lod {
+            level: LodLevel::Full,
+            phase,
+            last_think: 0,
+            next_think: 0,
+            steer: None,
+        }
+    }
+
+    pub fn due(&self, tick: u64) -> bool {
+        tick >= self.next_think
+    }
+
+    /// ticks since the last decision, for anything that counts time by decisions
+    pub fn elapsed(&self, tick: u64) -> u32 {
+        tick.saturating_sub(self.last_think)
+            .clamp(1, LodLevel::Distant.interval()) as u32
+    }
+
+    pub fn thought(&mut self, tick: u64, steer: (Vec3, f32)) {
+        let interval = self.level.interval();
+        let next = tick + 1;
+        self.last_think = tick;
+        self.next_think = next + (interval - (next + self.phase) % interval) % interval;
+        self.steer = Some(steer);
+    }
+}
+
+/// Picks each AI's level of detail from its distance to the nearest focus.
+/// Without a focus everything is simulated in full.
+pub fn lod_system(
+    pool: Res<ComputeTaskPool>,
+    clock: Res<SimClock>,
+    focuses: Query<&Transform, With<LodFocus>>,
+    mut query: Query<(&Transform, &mut Lod), With<AI>>,
+) {
+    let focuses: Vec<Vec3> = focuses.iter().map(|focus| focus.translation).collect();
+
+    query.par_for_each_mut(&pool, BATCH_SIZE, |(transform, mut lod)| {
+        let distance = focuses
+            .iter()
+            .map(|focus| focus.distance(transform.translation))
+            .reduce(f32::min)
+            .unwrap_or(0.0);
+
+        let mut level = LodLevel::at(distance);
+        if level > lod.level {
+            level = LodLevel::at(distance - HYSTERESIS).max(lod.level);
+        }
+
+        if level != lod.level {
+            // coming closer, think straight away rather than finishing the longer wait
+            if level < lod.level {
+                lod.next_think = clock.tick;
+            }
+            lod.level = level;
+        }
+    });
+}
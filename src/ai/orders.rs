@@ -0,0 +1,178 @@
+use bevy::prelude::*;
+
+use crate::autopilot::{Autopilot, LateralMode, VerticalMode};
+use crate::formation::Wingman;
+use crate::recording::Recorder;
+use crate::{Controls, Player, AI};
+
+/// radius of the racetrack flown while holding position
+const HOLD_RADIUS: f32 = 1500.0;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Order {
+    /// weapons free, pick targets as normal
+    EngageBandits,
+    AttackTarget(Entity),
+    /// only engage hostiles near the given aircraft
+    Cover(Entity),
+    /// break off and return to formation
+    Rejoin,
+    /// orbit a point
+    HoldPosition(Vec3),
+    ReturnToBase,
+}
+
+impl Order {
+    /// whether targeting should leave this aircraft's target alone
+    pub fn holds_fire(&self) -> bool {
+        matches!(
+            self,
+            Order::Rejoin | Order::HoldPosition(_) | Order::ReturnToBase
+        )
+    }
+}
+
+/// The last order given to a wingman by its flight lead
+#[derive(Component)]
+pub struct WingmanOrder(pub Order);
+
+/// The player's radio menu, which takes over the number keys while it's open
+#[derive(Default)]
+pub struct RadioMenu {
+    pub open: bool,
+    /// acknowledgement of the last order, for the HUD
+    pub last_call: Option<String>,
+}
+
+pub const RADIO_OPTIONS: [&str; 6] = [
+    "Attack my target",
+    "Engage bandits",
+    "Cover me",
+    "Rejoin",
+    "Hold position",
+    "Return to base",
+];
+
+const RADIO_KEYS: [KeyCode; 6] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+];
+
+/// Tab opens the menu and the number keys pick a call, which is given on the next tick, where
+/// it's recorded along with the other controls
+pub fn radio_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    recorder: Res<Recorder>,
+    mut menu: ResMut<RadioMenu>,
+    mut controls: ResMut<Controls>,
+) {
+    // the recording makes the calls on playback
+    if let Recorder::Playing { .. } = *recorder {
+        return;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Tab) {
+        menu.open = !menu.open;
+        return;
+    }
+    if !menu.open {
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        menu.open = false;
+        return;
+    }
+
+    if let Some(choice) = RADIO_KEYS
+        .iter()
+        .position(|key| keyboard_input.just_pressed(*key))
+    {
+        menu.open = false;
+        controls.radio = Some(choice);
+    }
+}
+
+/// Gives the player's wingmen the call picked from the radio menu
+pub fn radio_call_system(
+    mut controls: ResMut<Controls>,
+    mut menu: ResMut<RadioMenu>,
+    player_query: Query<(Entity, &Player)>,
+    mut wingmen: Query<(
+        &Transform,
+        &Wingman,
+        &mut AI,
+        &mut WingmanOrder,
+        Option<&mut Autopilot>,
+    )>,
+) {
+    let choice = match controls.radio.take() {
+        Some(choice) => choice,
+        None => return,
+    };
+
+    let (player_entity, player) = player_query.single();
+
+    let order = match choice {
+        0 => match player.target {
+            Some(target) => Order::AttackTarget(target),
+            None => {
+                menu.last_call = Some("Flight: no target".to_string());
+                return;
+            }
+        },
+        1 => Order::EngageBandits,
+        2 => Order::Cover(player_entity),
+        3 => Order::Rejoin,
+        // each wingman holds around where it is, filled in below
+        4 => Order::HoldPosition(Vec3::ZERO),
+        _ => Order::ReturnToBase,
+    };
+
+    let mut acknowledged = 0;
+    for (transform, wingman, mut ai, mut wingman_order, autopilot) in wingmen.iter_mut() {
+        if wingman.lead != player_entity {
+            continue;
+        }
+
+        wingman_order.0 = match order {
+            Order::HoldPosition(_) => Order::HoldPosition(transform.translation),
+            order => order,
+        };
+
+        match wingman_order.0 {
+            Order::AttackTarget(target) => ai.target = Some(target),
+            Order::HoldPosition(point) => {
+                ai.target = None;
+                if let Some(mut autopilot) = autopilot {
+                    autopilot.waypoints = hold_pattern(point);
+                    autopilot.active_waypoint = 0;
+                    autopilot.set_lateral(LateralMode::Navigation);
+                    autopilot.set_vertical(VerticalMode::Altitude(point.y));
+                }
+            }
+            order if order.holds_fire() => ai.target = None,
+            _ => {}
+        }
+
+        acknowledged += 1;
+    }
+
+    menu.last_call = Some(if acknowledged > 0 {
+        format!("Flight: copy, {}", RADIO_OPTIONS[choice].to_lowercase())
+    } else {
+        "No wingmen".to_string()
+    });
+}
+
+fn hold_pattern(centre: Vec3) -> Vec<Vec3> {
+    vec![
+        centre + Vec3::new(0.0, 0.0, -HOLD_RADIUS),
+        centre + Vec3::new(HOLD_RADIUS, 0.0, 0.0),
+        centre + Vec3::new(0.0, 0.0, HOLD_RADIUS),
+        centre + Vec3::new(-HOLD_RADIUS, 0.0, 0.0),
+    ]
+}
@@ -0,0 +1,406 @@
+use std::cmp::Reverse;
+use std::sync::Mutex;
+
+use bevy::{prelude::*, tasks::ComputeTaskPool};
+
+use super::lod::Lod;
+use super::skill::Skill;
+use super::BATCH_SIZE;
+use crate::faction::{Faction, Factions};
+use crate::sim_time::{SimClock, SimId};
+use crate::spatial::{Domain, SpatialEntry, SpatialIndex};
+use crate::terrain::HeightMap;
+
+/// ticks between sensor sweeps, staggered between aircraft
+const SWEEP_TICKS: u64 = 15;
+/// tracks not refreshed for this long are dropped
+const TRACK_MEMORY_TICKS: u64 = 600;
+/// RWR bearings are only good while the emitter keeps painting us
+const RWR_MEMORY_TICKS: u64 = 120;
+/// more tracks than this and only the closest are kept
+const MAX_TRACKS: usize = 32;
+/// fraction of the visual range an aircraft nose or tail on can be spotted at
+const VISUAL_HEAD_ON: f32 = 0.5;
+/// fraction of the visual range the pilot sees at behind the wing line
+const VISUAL_REAR: f32 = 0.6;
+/// fraction of the visual range a vehicle or site on the ground can be spotted at
+const VISUAL_SURFACE: f32 = 0.4;
+/// emitters further off than this are too faint for an RWR to pick up
+const RWR_RANGE: f32 = 15000.0;
+/// targets moving slower than this along a radar's line of sight hide in the ground clutter
+pub const NOTCH_SPEED: f32 = 8.0;
+/// an RWR only gives a rough range from signal strength, rounded up to this
+const RWR_RANGE_BAND: f32 = 1000.0;
+
+/// A flat layer of cloud that blocks the view through it, but not radar
+pub struct CloudLayer {
+    pub base: f32,
+    pub top: f32,
+}
+
+impl Default for CloudLayer {
+    fn default() -> Self {
+        CloudLayer {
+            base: 4000.0,
+            top: 4600.0,
+        }
+    }
+}
+
+impl CloudLayer {
+    /// whether the line between two points passes through, or starts or ends in, the cloud
+    fn blocks(&self, a: Vec3, b: Vec3) -> bool {
+        let (low, high) = (a.y.min(b.y), a.y.max(b.y));
+        high >= self.base && low <= self.top
+    }
+}
+
+/// A search radar, on an aircraft or a ground site
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Radar {
+    pub range: f32,
+    /// half angle of its scan, PI for one that looks all the way round. An RWR only picks it up
+    /// inside this too.
+    pub cone: f32,
+}
+
+impl Radar {
+    pub const FIGHTER: Radar = Radar {
+        range: 15000.0,
+        cone: 1.0,
+    };
+
+    /// whether `target` is inside the scan of this radar at `position` pointing along `forward`
+    pub fn covers(&self, position: Vec3, forward: Vec3, target: Vec3) -> bool {
+        let offset = target - position;
+        offset.length() < self.range && forward.dot(offset.normalize_or_zero()) >= self.cone.cos()
+    }
+
+    /// whether this radar holds `target` as a track; an air to air radar can't pick anything out on
+    /// the ground, or anything low and moving across its beam against it
+    pub fn tracks(&self, position: Vec3, forward: Vec3, target: &SpatialEntry) -> bool {
+        target.domain == Domain::Air
+            && self.covers(position, forward, target.position)
+            && !notched(position, target)
+    }
+}
+
+/// Whether a radar at `position` looking down loses `target` in the ground clutter behind it
+fn notched(position: Vec3, target: &SpatialEntry) -> bool {
+    let line_of_sight = (target.position - position).normalize_or_zero();
+    target.velocity.dot(line_of_sight).abs() < NOTCH_SPEED && target.position.y < position.y
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Sensor {
+    /// bearing and a rough range from a radar painting us
+    Rwr,
+    /// talked onto it by the flight lead or a controller
+    Comms,
+    Radar,
+    Visual,
+}
+
+impl Sensor {
+    /// whether the track is good enough to fight
+    pub fn precise(self) -> bool {
+        self != Sensor::Rwr
+    }
+}
+
+/// What an AI believes about an aircraft or ground unit, as of when it was last detected
+pub struct Track {
+    /// last known state, dead reckoned since
+    pub entry: SpatialEntry,
+    pub sensor: Sensor,
+    pub last_seen: u64,
+}
+
+/// Everything an AI knows is out there; it only ever fights what's in here
+#[derive(Component, Default)]
+pub struct Contacts {
+    /// sorted by `SimId`, so they're always visited in the same order
+    tracks: Vec<Track>,
+}
+
+impl Contacts {
+    pub fn iter(&self) -> impl Iterator<Item = &Track> {
+        self.tracks.iter()
+    }
+
+    pub fn track(&self, entity: Entity) -> Option<&Track> {
+        self.tracks
+            .iter()
+            .find(|track| track.entry.entity == entity)
+    }
+
+    /// a track precise enough to fight
+    pub fn get(&self, entity: Entity) -> Option<&SpatialEntry> {
+        self.track(entity)
+            .filter(|track| track.sensor.precise())
+            .map(|track| &track.entry)
+    }
+
+    /// Drops the track on something that's no longer there to be seen
+    pub fn forget(&mut self, entity: Entity) {
+        self.tracks.retain(|track| track.entry.entity != entity);
+    }
+
+    /// Records a detection, unless it's an RWR bearing on something already tracked precisely
+    fn detect(&mut self, entry: SpatialEntry, sensor: Sensor, tick: u64) {
+        let track = Track {
+            entry,
+            sensor,
+            last_seen: tick,
+        };
+        match self
+            .tracks
+            .binary_search_by_key(&entry.id, |track| track.entry.id)
+        {
+            Ok(index) => {
+                let existing = &mut self.tracks[index];
+                // an RWR bearing says nothing new about something we can see
+                if sensor.precise() || !existing.sensor.precise() {
+                    *existing = track;
+                }
+            }
+            Err(index) => self.tracks.insert(index, track),
+        }
+    }
+
+    /// Folds detections pushed on the end back into the sorted tracks, keeping the best track of
+    /// each aircraft and only the closest aircraft if there are too many
+    fn merge(&mut self, position: Vec3) {
+        // precise before vague and newest first, so dedup keeps the best
+        self.tracks.sort_by_key(|track| {
+            (
+                track.entry.id,
+                !track.sensor.precise(),
+                Reverse(track.last_seen),
+            )
+        });
+        self.tracks.dedup_by_key(|track| track.entry.id);
+        self.keep_closest(position);
+    }
+
+    /// Drops all but the closest aircraft if there are too many
+    fn keep_closest(&mut self, position: Vec3) {
+        if self.tracks.len() > MAX_TRACKS {
+            let distance = |track: &Track| track.entry.position.distance_squared(position);
+            self.tracks
+                .select_nth_unstable_by(MAX_TRACKS, |a, b| distance(a).total_cmp(&distance(b)));
+            self.tracks.truncate(MAX_TRACKS);
+            self.tracks.sort_unstable_by_key(|track| track.entry.id);
+        }
+    }
+
+    /// Records RWR bearings on the `emitters` painting us at `position`, given in `SimId` order,
+    /// except on those already tracked precisely
+    fn warned<'a>(
+        &mut self,
+        position: Vec3,
+        emitters: impl Iterator<Item = &'a SpatialEntry>,
+        tick: u64,
+    ) {
+        let mut existing = std::mem::take(&mut self.tracks).into_iter().peekable();
+        for emitter in emitters {
+            while let Some(track) = existing.next_if(|track| track.entry.id < emitter.id) {
+                self.tracks.push(track);
+            }
+            match existing.next_if(|track| track.entry.id == emitter.id) {
+                Some(track) if track.sensor.precise() => self.tracks.push(track),
+                _ => self.tracks.push(Track {
+                    entry: rwr_entry(position, emitter),
+                    sensor: Sensor::Rwr,
+                    last_seen: tick,
+                }),
+            }
+        }
+        self.tracks.extend(existing);
+        self.keep_closest(position);
+    }
+
+    /// Records something someone else has called out, until our own sensors pick it up
+    pub fn called_out(&mut self, entry: SpatialEntry, tick: u64) {
+        let own = self
+            .track(entry.entity)
+            .is_some_and(|track| matches!(track.sensor, Sensor::Radar | Sensor::Visual));
+        if !own {
+            self.detect(entry, Sensor::Comms, tick);
+        }
+    }
+}
+
+/// Where an RWR at `position` puts `emitter`: on its bearing, and only roughly how far off
+fn rwr_entry(position: Vec3, emitter: &SpatialEntry) -> SpatialEntry {
+    let offset = emitter.position - position;
+    let line_of_sight = offset.normalize_or_zero();
+    let banded = (offset.length() / RWR_RANGE_BAND).ceil() * RWR_RANGE_BAND;
+    SpatialEntry {
+        position: position + line_of_sight * banded,
+        forward: -line_of_sight,
+        velocity: Vec3::ZERO,
+        ..*emitter
+    }
+}
+
+/// Sweeps each AI's sensors over the hostiles around it, dead reckons what it's lost sight of,
+/// and forgets what it hasn't seen for a while. Eyes look all round out to the awareness radius
+/// and a radar only along its cone, so a sweep never searches further than it can see. RWR
+/// warnings are worked out from the emitter's side, as it sweeps, then handed to whoever it
+/// painted.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn sensor_system(
+    pool: Res<ComputeTaskPool>,
+    clock: Res<SimClock>,
+    index: Res<SpatialIndex>,
+    factions: Res<Factions>,
+    clouds: Res<CloudLayer>,
+    height_map: Res<HeightMap>,
+    mut query: Query<(
+        Entity,
+        &SimId,
+        &Transform,
+        &Faction,
+        &Skill,
+        &Lod,
+        Option<&Radar>,
+        &mut Contacts,
+    )>,
+    emitters: Query<(Entity, &Radar), Without<Contacts>>,
+) {
+    let tick = clock.tick;
+    // (painted, emitter's id, emitter) for every hostile an emitter's sweep took in this tick
+    let warnings = Mutex::new(Vec::new());
+
+    query.par_for_each_mut(&pool, BATCH_SIZE, |item| {
+        let (entity, id, transform, faction, skill, lod, radar, mut contacts) = item;
+        if !lod.due(tick) {
+            return;
+        }
+        let elapsed = lod.elapsed(tick) as f32;
+
+        contacts.tracks.retain(|track| {
+            let memory = match track.sensor {
+                Sensor::Rwr => RWR_MEMORY_TICKS,
+                _ => TRACK_MEMORY_TICKS,
+            };
+            // wreckage is seen going down, so the destroyed are forgotten straight away
+            tick - track.last_seen < memory && index.get(track.entry.entity).is_some()
+        });
+        for track in contacts.tracks.iter_mut() {
+            if track.last_seen != tick {
+                track.entry.position += track.entry.velocity * elapsed;
+            }
+        }
+
+        // sweeps are staggered, and aircraft thinking less often sweep on whichever of their
+        // decisions falls in the window
+        if (tick + id.0 as u64) % SWEEP_TICKS >= lod.level.interval() {
+            return;
+        }
+
+        let position = transform.translation;
+        let forward = transform.forward();
+        let hostile = |entry: &&SpatialEntry| {
+            entry.entity != entity && factions.hostile(*faction, entry.faction)
+        };
+
+        // seen first, so merge keeps it over a radar track of the same aircraft
+        for entry in index
+            .within_radius(position, skill.awareness_radius)
+            .filter(hostile)
+        {
+            let line_of_sight = (entry.position - position).normalize_or_zero();
+            let mut visual_range = match entry.domain {
+                // side on shows the whole planform, nose or tail on hardly anything
+                Domain::Air => {
+                    let aspect = 1.0 - entry.forward.dot(line_of_sight).abs();
+                    skill.awareness_radius * (VISUAL_HEAD_ON + (1.0 - VISUAL_HEAD_ON) * aspect)
+                }
+                Domain::Surface => skill.awareness_radius * VISUAL_SURFACE,
+            };
+            if forward.dot(line_of_sight) < 0.0 {
+                visual_range *= VISUAL_REAR;
+            }
+            if entry.position.distance(position) < visual_range
+                && !clouds.blocks(position, entry.position)
+                && height_map.line_of_sight(position, entry.position)
+            {
+                contacts.tracks.push(Track {
+                    entry: *entry,
+                    sensor: Sensor::Visual,
+                    last_seen: tick,
+                });
+                // in a crowd, weed out the far ones as we go rather than piling up thousands
+                if contacts.tracks.len() >= MAX_TRACKS * 4 {
+                    contacts.merge(position);
+                }
+            }
+        }
+
+        if let Some(radar) = radar {
+            let mut painted = Vec::new();
+            for entry in index
+                .within_cone(position, forward, radar.cone, radar.range)
+                .filter(hostile)
+            {
+                // a radar can no more see through hills and buildings than eyes can
+                if !height_map.line_of_sight(position, entry.position) {
+                    continue;
+                }
+                if entry.position.distance(position) < RWR_RANGE
+                    && radar.covers(position, forward, entry.position)
+                {
+                    painted.push((entry.entity, *id, entity));
+                }
+                if radar.tracks(position, forward, entry) {
+                    contacts.tracks.push(Track {
+                        entry: *entry,
+                        sensor: Sensor::Radar,
+                        last_seen: tick,
+                    });
+                    if contacts.tracks.len() >= MAX_TRACKS * 4 {
+                        contacts.merge(position);
+                    }
+                }
+            }
+            warnings.lock().unwrap().append(&mut painted);
+        }
+
+        contacts.merge(position);
+    });
+
+    // the player, ground sites and ships paint aircraft too, but keep no contacts of their own
+    let mut warnings = warnings.into_inner().unwrap();
+    for (emitter, radar) in emitters.iter() {
+        let entry = match index.get(emitter) {
+            Some(entry) if (tick + entry.id.0 as u64).is_multiple_of(SWEEP_TICKS) => entry,
+            _ => continue,
+        };
+        warnings.extend(
+            index
+                .within_cone(entry.position, entry.forward, radar.cone, RWR_RANGE)
+                .filter(|target| {
+                    factions.hostile(entry.faction, target.faction)
+                        && radar.covers(entry.position, entry.forward, target.position)
+                        && height_map.line_of_sight(entry.position, target.position)
+                })
+                .map(|target| (target.entity, entry.id, emitter)),
+        );
+    }
+
+    // sorted, so each aircraft can find the emitters painting it, in `SimId` order
+    warnings.sort_unstable();
+    query.par_for_each_mut(&pool, BATCH_SIZE, |item| {
+        let (entity, _, transform, .., mut contacts) = item;
+        let start = warnings.partition_point(|&(painted, ..)| painted < entity);
+        let end = warnings.partition_point(|&(painted, ..)| painted <= entity);
+        if start < end {
+            let emitters = warnings[start..end]
+                .iter()
+                .filter_map(|&(_, _, emitter)| index.get(emitter));
+            contacts.warned(transform.translation, emitters, tick);
+        }
+    });
+}
@@ -0,0 +1,129 @@
+use std::{collections::HashMap, fs};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::bfm::Manoeuvre;
+
+const PROFILES_PATH: &str = "assets/config/ai_profiles.ron";
+
+/// How well an AI flies and fights
+#[derive(Component, Clone, Deserialize)]
+pub struct Skill {
+    /// ticks a new threat has to be in view before it's reacted to
+    pub reaction_ticks: u32,
+    /// how far off the aim point the AI steers, as a fraction of the range to it
+    pub aim_error: f32,
+    /// fraction of the airframe's turn rate the pilot can stand
+    pub g_tolerance: f32,
+    /// range the pilot spots an aircraft at side on in clear air, and how far off threats are taken seriously
+    pub awareness_radius: f32,
+    /// 0 to 1, how readily the AI commits to a fight and how long it stays in one
+    pub aggression: f32,
+    /// airspeed in units per tick
+    pub cruise_speed: f32,
+    /// the manoeuvres the AI knows, anything else falls back to a simpler one it does
+    pub manoeuvres: Vec<Manoeuvre>,
+}
+
+impl Default for Skill {
+    fn default() -> Self {
+        Skill {
+            reaction_ticks: 15,
+            aim_error: 0.02,
+            g_tolerance: 1.0,
+            awareness_radius: 8000.0,
+            aggression: 0.5,
+            cruise_speed: 50.0,
+            manoeuvres: vec![
+                Manoeuvre::LeadPursuit,
+                Manoeuvre::PurePursuit,
+                Manoeuvre::LagPursuit,
+                Manoeuvre::HighYoYo,
+                Manoeuvre::LowYoYo,
+                Manoeuvre::Immelmann,
+                Manoeuvre::SplitS,
+                Manoeuvre::BreakTurn,
+                Manoeuvre::Scissors,
+                Manoeuvre::BarrelRollDefence,
+            ],
+        }
+    }
+}
+
+impl Skill {
+    /// the closest manoeuvre to `manoeuvre` that this AI can fly
+    pub fn repertoire(&self, mut manoeuvre: Manoeuvre) -> Manoeuvre {
+        while !self.manoeuvres.contains(&manoeuvre) {
+            manoeuvre = match manoeuvre.simpler() {
+                Some(simpler) => simpler,
+                None => break,
+            };
+        }
+        manoeuvre
+    }
+}
+
+/// Every skill profile, and which ones the mission hands to which aircraft
+#[derive(Deserialize)]
+pub struct SkillProfiles {
+    pub profiles: HashMap<String, Skill>,
+    /// profile for any aircraft not otherwise assigned one
+    pub default: String,
+    /// profile for the player's wingmen
+    pub wingmen: String,
+    /// for each faction by name, the profile of each of its flights in spawn order,
+    /// repeating if there are more flights
+    #[serde(default)]
+    pub flights: HashMap<String, Vec<String>>,
+}
+
+impl Default for SkillProfiles {
+    fn default() -> Self {
+        SkillProfiles {
+            profiles: HashMap::from([("default".to_string(), Skill::default())]),
+            default: "default".to_string(),
+            wingmen: "default".to_string(),
+            flights: HashMap::new(),
+        }
+    }
+}
+
+impl SkillProfiles {
+    pub fn load() -> Self {
+        match fs::read_to_string(PROFILES_PATH)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| ron::from_str(&contents).map_err(|err| err.to_string()))
+        {
+            Ok(profiles) => profiles,
+            Err(err) => {
+                error!("Could not load {}: {}", PROFILES_PATH, err);
+                SkillProfiles::default()
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Skill {
+        if let Some(skill) = self.profiles.get(name) {
+            return skill.clone();
+        }
+
+        warn!("No skill profile named {}", name);
+        self.profiles
+            .get(&self.default)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// profile for the `index`th flight of the named faction
+    pub fn flight(&self, faction: &str, index: usize) -> Skill {
+        match self
+            .flights
+            .get(faction)
+            .and_then(|flights| flights.get(index % flights.len().max(1)))
+        {
+            Some(name) => self.get(name),
+            None => self.get(&self.default),
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use bevy::{prelude::*, tasks::ComputeTaskPool};
+use serde::Deserialize;
+
+use super::lod::Lod;
+use super::orders::{Order, WingmanOrder};
+use super::sensors::Contacts;
+use super::skill::Skill;
+use super::utility::{Factor, UtilityBehaviour};
+use super::BATCH_SIZE;
+use crate::autopilot::{Autopilot, LateralMode, VerticalMode};
+use crate::damage::Damage;
+use crate::sim_time::SimClock;
+use crate::spatial::Domain;
+use crate::utils;
+use crate::AI;
+
+/// a hostile behind us and pointing at us is a threat within this fraction of our awareness radius
+const THREAT_AWARENESS: f32 = 0.15;
+/// half angle of the cone behind us that threats are looked for in
+const THREAT_CONE: f32 = 1.25;
+/// hostiles further than this aren't counted as the nearest
+const NEAREST_HOSTILE_RANGE: f32 = 10000.0;
+/// fuel used per tick at full speed, roughly five minutes of flight from full
+const FUEL_BURN: f32 = 0.00005;
+const BASE_RADIUS: f32 = 500.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+pub enum AIMode {
+    /// flying the autopilot's waypoints, looking for trouble
+    Patrol,
+    /// closing on a target that is still some way off
+    Intercept,
+    /// dogfighting the target
+    Engage,
+    /// breaking away from a hostile on our tail
+    Evade,
+    /// running away from the fight
+    Disengage,
+    /// bingo fuel, heading home to refuel
+    ReturnToBase,
+}
+
+#[derive(Component)]
+pub struct AIState {
+    pub mode: AIMode,
+    /// the hostile that triggered an evade or disengage
+    pub threat: Option<Entity>,
+    ticks_in_mode: u32,
+    /// consecutive ticks a threat has been in view, so the pilot's reaction time can pass
+    threat_ticks: u32,
+}
+
+impl Default for AIState {
+    fn default() -> Self {
+        AIState {
+            mode: AIMode::Patrol,
+            threat: None,
+            ticks_in_mode: 0,
+            threat_ticks: 0,
+        }
+    }
+}
+
+/// Where an AI aircraft refuels
+#[derive(Component)]
+pub struct HomeBase(pub Vec3);
+
+/// What an AI knows about its surroundings this tick
+struct Situation {
+    target_range: Option<f32>,
+    threat: Option<Entity>,
+    nearest_hostile_range: f32,
+    health: f32,
+    fuel: f32,
+    at_base: bool,
+    /// the flight lead has sent us home
+    ordered_home: bool,
+    aggression: f32,
+}
+
+impl Situation {
+    fn factor(&self, factor: Factor, state: &AIState) -> f32 {
+        let flag = |value: bool| value as u8 as f32;
+
+        match factor {
+            Factor::TargetRange => self.target_range.unwrap_or(f32::INFINITY),
+            Factor::NearestHostileRange => self.nearest_hostile_range,
+            Factor::Health => self.health,
+            Factor::Fuel => self.fuel,
+            Factor::ThreatInView => flag(self.threat.is_some()),
+            Factor::AtBase => flag(self.at_base),
+            Factor::OrderedHome => flag(self.ordered_home),
+            Factor::Aggression => self.aggression,
+            Factor::TicksInMode => state.ticks_in_mode as f32,
+            Factor::InMode(mode) => flag(state.mode == mode),
+            Factor::Committed => flag(matches!(
+                state.mode,
+                AIMode::Intercept | AIMode::Engage | AIMode::Evade
+            )),
+        }
+    }
+}
+
+/// Works out each AI's mode from threat, health, fuel and geometry, as the behaviour file decides
+#[allow(clippy::type_complexity)]
+pub fn ai_state_system(
+    pool: Res<ComputeTaskPool>,
+    clock: Res<SimClock>,
+    behaviour: Res<UtilityBehaviour>,
+    mut query: Query<(
+        &Transform,
+        &Contacts,
+        &mut AI,
+        &Damage,
+        &mut AIState,
+        &Skill,
+        &HomeBase,
+        &Lod,
+        Option<&mut Autopilot>,
+        Option<&mut WingmanOrder>,
+    )>,
+) {
+    query.par_for_each_mut(&pool, BATCH_SIZE, |item| {
+        let (transform, contacts, mut ai, damage, mut state, skill, home, lod, autopilot, order) =
+            item;
+        if !lod.due(clock.tick) {
+            return;
+        }
+        let elapsed = lod.elapsed(clock.tick);
+        let position = transform.translation;
+        let forward = transform.forward();
+
+        let burn = FUEL_BURN * utils::clamp(ai.velocity.length() / 50.0, 0.5, 1.5);
+        // shot-through tanks leak whatever the engine's doing
+        let burn = burn + damage.fuel_leak();
+        ai.fuel = (ai.fuel - burn * elapsed as f32).max(0.0);
+
+        let target_range = ai
+            .target
+            .and_then(|target| contacts.get(target))
+            .map(|target| target.position.distance(position));
+
+        // anything in the air we know of in the cone behind us, pointing at us, is a threat
+        let threat_range = skill.awareness_radius * THREAT_AWARENESS;
+        let air = || {
+            contacts
+                .iter()
+                .map(|track| &track.entry)
+                .filter(|entry| entry.domain == Domain::Air)
+        };
+        let threat = air()
+            .filter(|hostile| {
+                let offset = hostile.position - position;
+                let direction = offset.normalize_or_zero();
+                offset.length() < threat_range
+                    && (-forward).dot(direction) >= THREAT_CONE.cos()
+                    && hostile.forward.dot(-direction) > 0.8
+            })
+            .min_by(|a, b| {
+                let a = a.position.distance_squared(position);
+                let b = b.position.distance_squared(position);
+                a.total_cmp(&b)
+            })
+            .map(|hostile| hostile.entity);
+
+        // but it takes the pilot a moment to react to it
+        state.threat_ticks = match threat {
+            Some(_) => state.threat_ticks + elapsed,
+            None => 0,
+        };
+        let threat = threat.filter(|_| state.threat_ticks > skill.reaction_ticks);
+
+        let nearest_hostile_range = air()
+            .map(|hostile| hostile.position.distance(position))
+            .filter(|&range| range < NEAREST_HOSTILE_RANGE)
+            .reduce(f32::min)
+            .unwrap_or(f32::INFINITY);
+
+        let at_base = (home.0 - position).length() < BASE_RADIUS;
+        if at_base && state.mode == AIMode::ReturnToBase {
+            ai.fuel = 1.0;
+        }
+
+        let mut ordered_home = false;
+        if let Some(mut order) = order {
+            if order.0 == Order::ReturnToBase {
+                // once home and refuelled, head back out to the lead
+                if at_base {
+                    order.0 = Order::Rejoin;
+                } else {
+                    ordered_home = true;
+                }
+            }
+        }
+
+        let situation = Situation {
+            target_range,
+            threat,
+            nearest_hostile_range,
+            health: damage.health(),
+            fuel: ai.fuel,
+            at_base,
+            ordered_home,
+            aggression: skill.aggression,
+        };
+
+        let mode = behaviour
+            .modes
+            .choose(state.mode, skill.aggression, |factor| {
+                situation.factor(factor, &state)
+            });
+        if situation.threat.is_some() {
+            state.threat = situation.threat;
+        } else if !matches!(mode, AIMode::Evade | AIMode::Disengage) {
+            state.threat = None;
+        }
+
+        if mode == state.mode {
+            state.ticks_in_mode += elapsed;
+            return;
+        }
+
+        state.mode = mode;
+        state.ticks_in_mode = 0;
+
+        // the autopilot flies the patrol route, everything else is steered directly
+        if let Some(mut autopilot) = autopilot {
+            if mode == AIMode::Patrol {
+                autopilot.set_lateral(LateralMode::Navigation);
+                autopilot.set_vertical(VerticalMode::Altitude(home.0.y));
+            } else {
+                autopilot.disengage();
+            }
+        }
+    });
+}
+
+/// Steering point and speed for the modes that don't involve fighting
+pub fn steering(
+    state: &AIState,
+    transform: &Transform,
+    home: &HomeBase,
+    threat_position: Option<Vec3>,
+    cruise_speed: f32,
+) -> Option<(Vec3, f32)> {
+    let position = transform.translation;
+
+    match state.mode {
+        AIMode::Disengage => {
+            let away = match threat_position {
+                Some(threat_position) => (position - threat_position).normalize_or_zero(),
+                None => (home.0 - position).normalize_or_zero(),
+            };
+            Some((position + away * 1000.0, cruise_speed * 1.2))
+        }
+        AIMode::ReturnToBase => Some((home.0, cruise_speed * 0.8)),
+        // evading is flown as a defensive manoeuvre by the BFM
+        AIMode::Patrol | AIMode::Intercept | AIMode::Engage | AIMode::Evade => None,
+    }
+}
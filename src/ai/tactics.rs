@@ -0,0 +1,321 @@
+use std::cmp::Reverse;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::lod::Lod;
+use super::sensors::{Contacts, Track};
+use super::utility::{TacticFactor, UtilityBehaviour};
+use crate::faction::{Faction, Factions};
+use crate::sim_time::{SimClock, SimId};
+use crate::spatial::{Domain, SpatialEntry, SpatialIndex};
+
+/// ticks between re-evaluations of a flight's tactic
+const REEVALUATE_TICKS: u64 = 60;
+/// hostiles within this range of the flight are part of its picture
+const PICTURE_RANGE: f32 = 12000.0;
+/// hostiles this close to the middle of the others are shared out when the flight sorts
+const COMMIT_RANGE: f32 = 5000.0;
+/// lateral distance each element aims to the side of the hostiles in a pincer
+const PINCER_OFFSET: f32 = 4000.0;
+/// lateral and vertical distances each element aims to the side of the hostiles in a bracket
+const BRACKET_OFFSET: f32 = 2000.0;
+const BRACKET_STACK: f32 = 300.0;
+/// ticks each element spends hot before swapping in the grinder
+const GRINDER_PERIOD: u64 = 600;
+/// distance the cold element of a grinder extends to
+const GRINDER_EXTEND: f32 = 3000.0;
+const STEER_DISTANCE: f32 = 1000.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+pub enum Tactic {
+    /// no hostiles about
+    Patrol,
+    /// elements split wide to hit the hostiles from both beams
+    Pincer,
+    /// elements split either side of the hostiles and above and below them
+    Bracket,
+    /// elements take turns to engage while the other extends and turns back in
+    Grinder,
+    /// everyone turns side on to the hostiles, to hide in their radar's doppler notch
+    Beam,
+    /// everyone turns tail to the hostiles to extend away
+    Drag,
+    /// members pick separate hostiles and merge
+    Sort,
+}
+
+/// A group of AI aircraft that fight together, owning its members
+#[derive(Component)]
+pub struct Flight {
+    pub faction: Faction,
+    /// lead first, then its wingmen
+    pub members: Vec<Entity>,
+    pub tactic: Tactic,
+    /// refreshed whenever the lead thinks, and flown from in between
+    picture: Option<Picture>,
+}
+
+impl Flight {
+    pub fn new(faction: Faction, members: Vec<Entity>) -> Self {
+        Flight {
+            faction,
+            members,
+            tactic: Tactic::Patrol,
+            picture: None,
+        }
+    }
+}
+
+/// What a flight wants from one of its members
+#[derive(Component)]
+pub struct FlightMember {
+    pub flight: Entity,
+    /// which half of the flight this member flies with when it splits
+    pub element: usize,
+    /// steering point, and speed as a fraction of cruise, while the flight manoeuvres as a group
+    pub steer: Option<(Vec3, f32)>,
+    /// the hostile this member was given when the flight sorted
+    pub assigned: Option<Entity>,
+}
+
+impl FlightMember {
+    pub fn new(flight: Entity, slot: usize) -> Self {
+        FlightMember {
+            flight,
+            element: slot / 2,
+            steer: None,
+            assigned: None,
+        }
+    }
+}
+
+/// What a flight can see of the hostiles around it
+struct Picture {
+    centroid: Vec3,
+    /// from the flight towards the hostiles
+    line_of_sight: Vec3,
+    range: f32,
+    hostiles: usize,
+    /// hostiles pointing at the flight
+    threats: usize,
+}
+
+fn centroid<'a>(entries: impl Iterator<Item = &'a SpatialEntry>) -> Option<Vec3> {
+    let (sum, count) = entries.fold((Vec3::ZERO, 0), |(sum, count), entry| {
+        (sum + entry.position, count + 1)
+    });
+    (count > 0).then(|| sum / count as f32)
+}
+
+/// Every hostile aircraft the flight's members have tracked between them, the freshest track of each
+fn shared_tracks(
+    members: &[Entity],
+    contacts: &Query<&Contacts>,
+    factions: &Factions,
+    faction: Faction,
+) -> Vec<SpatialEntry> {
+    let mut tracks: Vec<&Track> = members
+        .iter()
+        .filter_map(|member| contacts.get(*member).ok())
+        .flat_map(|contacts| contacts.iter())
+        .filter(|track| {
+            track.sensor.precise()
+                && track.entry.domain == Domain::Air
+                && factions.hostile(faction, track.entry.faction)
+        })
+        .collect();
+    tracks.sort_by_key(|track| (track.entry.id, Reverse(track.last_seen)));
+    tracks.dedup_by_key(|track| track.entry.id);
+    tracks.into_iter().map(|track| track.entry).collect()
+}
+
+fn picture(hostiles: &[SpatialEntry], flight_centre: Vec3) -> Option<Picture> {
+    // walked twice rather than collected, as the threats depend on where the hostiles' centre is
+    let hostiles = || {
+        hostiles
+            .iter()
+            .filter(move |entry| entry.position.distance(flight_centre) < PICTURE_RANGE)
+    };
+    let hostile_centre = centroid(hostiles())?;
+
+    let offset = hostile_centre - flight_centre;
+    let line_of_sight = offset.normalize_or_zero();
+    let (count, threats) = hostiles().fold((0, 0), |(count, threats), hostile| {
+        let threat = hostile.forward.dot(-line_of_sight) > 0.9;
+        (count + 1, threats + threat as usize)
+    });
+
+    Some(Picture {
+        centroid: hostile_centre,
+        line_of_sight,
+        range: offset.length(),
+        hostiles: count,
+        threats,
+    })
+}
+
+impl Picture {
+    fn factor(&self, factor: TacticFactor, members: usize, average_health: f32) -> f32 {
+        match factor {
+            TacticFactor::Range => self.range,
+            TacticFactor::Hostiles => self.hostiles as f32,
+            TacticFactor::Threats => self.threats as f32,
+            TacticFactor::Members => members as f32,
+            TacticFactor::Odds => self.hostiles as f32 - members as f32,
+            TacticFactor::AverageHealth => average_health,
+        }
+    }
+}
+
+/// Hands each member a different hostile, matching them left to right across the line of sight
+fn sort(
+    hostiles: &[SpatialEntry],
+    picture: &Picture,
+    members: &[&SpatialEntry],
+) -> Vec<Option<Entity>> {
+    let across = picture.line_of_sight.cross(Vec3::Y);
+    let lateral = |entry: &&SpatialEntry| entry.position.dot(across);
+
+    let mut hostiles: Vec<_> = hostiles
+        .iter()
+        .filter(|entry| entry.position.distance(picture.centroid) < COMMIT_RANGE)
+        .collect();
+    hostiles.sort_by(|a, b| lateral(a).total_cmp(&lateral(b)));
+
+    let mut order: Vec<_> = (0..members.len()).collect();
+    order.sort_by(|&a, &b| lateral(&members[a]).total_cmp(&lateral(&members[b])));
+
+    // with fewer hostiles than members, the spares double up from the left
+    let mut assigned = vec![None; members.len()];
+    if !hostiles.is_empty() {
+        for (rank, &member) in order.iter().enumerate() {
+            assigned[member] = Some(hostiles[rank % hostiles.len()].entity);
+        }
+    }
+    assigned
+}
+
+/// Steering point and speed factor for one member of a flight flying `tactic`
+fn steer(
+    tactic: Tactic,
+    picture: &Picture,
+    member: &SpatialEntry,
+    element: usize,
+    tick: u64,
+) -> Option<(Vec3, f32)> {
+    let position = member.position;
+    let across = picture.line_of_sight.cross(Vec3::Y).normalize_or_zero();
+    let side = if element == 0 { -1.0 } else { 1.0 };
+
+    match tactic {
+        Tactic::Patrol | Tactic::Sort => None,
+        Tactic::Pincer => Some((picture.centroid + across * side * PINCER_OFFSET, 1.1)),
+        Tactic::Bracket => Some((
+            picture.centroid + across * side * BRACKET_OFFSET + Vec3::Y * side * BRACKET_STACK,
+            1.0,
+        )),
+        Tactic::Grinder => {
+            let hot = (tick / GRINDER_PERIOD) as usize % 2;
+            if element == hot {
+                None
+            } else {
+                Some((
+                    picture.centroid - picture.line_of_sight * (picture.range + GRINDER_EXTEND),
+                    1.0,
+                ))
+            }
+        }
+        Tactic::Beam => {
+            // whichever beam is nearer our current heading
+            let beam = if member.forward.dot(across) >= 0.0 {
+                across
+            } else {
+                -across
+            };
+            Some((position + beam * STEER_DISTANCE, 1.0))
+        }
+        Tactic::Drag => Some((position - picture.line_of_sight * STEER_DISTANCE, 1.2)),
+    }
+}
+
+/// Re-evaluates each flight's tactic and tells its members what to do about it
+#[allow(clippy::too_many_arguments)]
+pub fn flight_system(
+    mut commands: Commands,
+    clock: Res<SimClock>,
+    index: Res<SpatialIndex>,
+    factions: Res<Factions>,
+    behaviour: Res<UtilityBehaviour>,
+    mut flights: Query<(Entity, &SimId, &mut Flight)>,
+    mut members: Query<&mut FlightMember>,
+    lods: Query<&Lod>,
+    contacts: Query<&Contacts>,
+) {
+    for (flight_entity, id, flight) in flights.iter_mut() {
+        let flight = flight.into_inner();
+        flight.members.retain(|member| index.get(*member).is_some());
+        if flight.members.is_empty() {
+            commands.entity(flight_entity).despawn();
+            continue;
+        }
+
+        let entries: Vec<_> = flight
+            .members
+            .iter()
+            .filter_map(|member| index.get(*member))
+            .collect();
+        let average_health =
+            entries.iter().map(|entry| entry.health).sum::<f32>() / entries.len() as f32;
+
+        // stagger re-evaluation so flights don't all change their minds on the same tick
+        let due = (clock.tick + id.0 as u64).is_multiple_of(REEVALUATE_TICKS);
+        let lead_thinking = lods
+            .get(flight.members[0])
+            .map_or(true, |lod| lod.due(clock.tick));
+        let mut hostiles = Vec::new();
+        if due || lead_thinking {
+            hostiles = shared_tracks(&flight.members, &contacts, &factions, flight.faction);
+            let flight_centre = centroid(entries.iter().copied()).unwrap_or_default();
+            flight.picture = picture(&hostiles, flight_centre);
+        }
+        let picture = &flight.picture;
+
+        if due {
+            // a flight has no pilot's aggression of its own, so it's taken as middling
+            let tactic = picture.as_ref().map_or(Tactic::Patrol, |picture| {
+                behaviour.tactics.choose(flight.tactic, 0.5, |factor| {
+                    picture.factor(factor, entries.len(), average_health)
+                })
+            });
+
+            if tactic != flight.tactic {
+                flight.tactic = tactic;
+
+                let assigned = match (picture, tactic) {
+                    (Some(picture), Tactic::Sort) => Some(sort(&hostiles, picture, &entries)),
+                    _ => None,
+                };
+                for (i, member) in flight.members.iter().enumerate() {
+                    if let Ok(mut member) = members.get_mut(*member) {
+                        member.assigned = assigned.as_ref().and_then(|assigned| assigned[i]);
+                    }
+                }
+            }
+        }
+
+        for (entry, member) in entries.iter().zip(&flight.members) {
+            if let Ok(mut member) = members.get_mut(*member) {
+                member.steer = picture.as_ref().and_then(|picture| {
+                    steer(flight.tactic, picture, entry, member.element, clock.tick)
+                });
+                // a sorted target that's gone frees the member to pick its own
+                if let Some(assigned) = member.assigned {
+                    if index.get(assigned).is_none() {
+                        member.assigned = None;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+
+use bevy::{prelude::*, tasks::ComputeTaskPool};
+
+use super::lod::Lod;
+use super::orders::{Order, WingmanOrder};
+use super::sensors::Contacts;
+use super::tactics::FlightMember;
+use super::BATCH_SIZE;
+use crate::faction::{Faction, Factions};
+use crate::sim_time::{SimClock, SimId};
+use crate::spatial::{Domain, SpatialEntry, SpatialIndex};
+use crate::utils;
+use crate::AI;
+
+/// ticks between re-evaluations of an AI's target
+const REEVALUATE_TICKS: u64 = 30;
+/// ranges are scored relative to this, anything further scores nothing for range
+const MAX_SCORED_RANGE: f32 = 10000.0;
+/// closure rates are scored relative to this, in units per tick
+const MAX_SCORED_CLOSURE: f32 = 100.0;
+const THREAT_RANGE: f32 = 3000.0;
+/// wingmen covering another aircraft only go after hostiles this close to it
+const COVER_RANGE: f32 = 3000.0;
+
+/// How much each factor counts towards a target's score
+pub struct TargetWeights {
+    pub range: f32,
+    /// being behind the target, looking at its tail
+    pub aspect: f32,
+    pub closure: f32,
+    /// the target is pointing at us
+    pub threat: f32,
+    /// the target is damaged
+    pub health: f32,
+    /// penalty per friendly already attacking the target
+    pub allocation: f32,
+    /// a new target has to beat the current one by this much before we switch
+    pub hysteresis: f32,
+}
+
+impl Default for TargetWeights {
+    fn default() -> Self {
+        TargetWeights {
+            range: 1.0,
+            aspect: 0.4,
+            closure: 0.2,
+            threat: 0.8,
+            health: 0.3,
+            allocation: 0.35,
+            hysteresis: 0.15,
+        }
+    }
+}
+
+pub fn score(
+    weights: &TargetWeights,
+    position: Vec3,
+    velocity: Vec3,
+    contact: &SpatialEntry,
+    attackers: u32,
+) -> f32 {
+    let offset = contact.position - position;
+    let range = offset.length();
+    let line_of_sight = offset.normalize_or_zero();
+
+    let range_score = 1.0 - utils::clamp(range / MAX_SCORED_RANGE, 0.0, 1.0);
+    // 1 when we're sat on its tail, -1 when it's coming at us head on
+    let aspect_score = contact.forward.dot(line_of_sight);
+    let closure = (velocity - contact.velocity).dot(line_of_sight);
+    let closure_score = utils::clamp(closure / MAX_SCORED_CLOSURE, -1.0, 1.0);
+    let threat_score = if range < THREAT_RANGE {
+        utils::clamp(contact.forward.dot(-line_of_sight), 0.0, 1.0)
+    } else {
+        0.0
+    };
+    let health_score = 1.0 - utils::clamp(contact.health / 100.0, 0.0, 1.0);
+
+    weights.range * range_score
+        + weights.aspect * aspect_score
+        + weights.closure * closure_score
+        + weights.threat * threat_score
+        + weights.health * health_score
+        - weights.allocation * attackers as f32
+}
+
+/// Keeps the `current` target unless the `best` scoring one beats it by the hysteresis margin,
+/// so close scores don't flip the target back and forth
+fn choose(
+    weights: &TargetWeights,
+    current: Option<(Entity, f32)>,
+    best: Option<(Entity, f32)>,
+) -> Option<Entity> {
+    match (best, current) {
+        (Some((_, best_score)), Some((current, current_score)))
+            if best_score < current_score + weights.hysteresis =>
+        {
+            Some(current)
+        }
+        (Some((best, _)), _) => Some(best),
+        (None, current) => current.map(|(current, _)| current),
+    }
+}
+
+/// Periodically re-scores the hostiles each AI has tracked and switches target when a better one
+/// appears. Allocation counts lag a tick: aircraft re-evaluating on the same tick don't see each
+/// other's picks, so they can pile onto one target until their next re-evaluation.
+#[allow(clippy::type_complexity)]
+pub fn targeting_system(
+    pool: Res<ComputeTaskPool>,
+    clock: Res<SimClock>,
+    weights: Res<TargetWeights>,
+    index: Res<SpatialIndex>,
+    factions: Res<Factions>,
+    mut query: Query<(
+        &SimId,
+        &Transform,
+        &Faction,
+        &mut AI,
+        &mut Contacts,
+        &Lod,
+        Option<&mut WingmanOrder>,
+        Option<&FlightMember>,
+    )>,
+) {
+    // how many of each faction are going after each target as the tick starts; staggering keeps
+    // aircraft re-evaluating on the same tick, and so missing each other's picks, rare
+    let mut attackers: HashMap<(Faction, Entity), u32> = HashMap::new();
+    for (_, _, faction, ai, _, _, _, _) in query.iter() {
+        if let Some(target) = ai.target {
+            *attackers.entry((*faction, target)).or_default() += 1;
+        }
+    }
+
+    let attacking =
+        |faction: Faction, entity: Entity| attackers.get(&(faction, entity)).copied().unwrap_or(0);
+
+    query.par_for_each_mut(&pool, BATCH_SIZE, |item| {
+        let (id, transform, faction, mut ai, mut contacts, lod, order, member) = item;
+        if !lod.due(clock.tick) {
+            return;
+        }
+        let faction = *faction;
+        let position = transform.translation;
+
+        // a target handed out when the flight sorted sticks until it's gone
+        if let Some(assigned) = member.and_then(|member| member.assigned) {
+            if let Some(entry) = index.get(assigned) {
+                contacts.called_out(*entry, clock.tick);
+                ai.target = Some(assigned);
+                return;
+            }
+        }
+
+        // orders from the flight lead override our own choice of target
+        let mut cover = None;
+        if let Some(mut order) = order {
+            match order.0 {
+                Order::AttackTarget(target) => match index.get(target) {
+                    Some(entry) => {
+                        contacts.called_out(*entry, clock.tick);
+                        ai.target = Some(target);
+                        return;
+                    }
+                    // the target is gone, go back to picking our own
+                    None => order.0 = Order::EngageBandits,
+                },
+                Order::Cover(covered) => cover = index.get(covered).map(|entry| entry.position),
+                order if order.holds_fire() => {
+                    ai.target = None;
+                    return;
+                }
+                _ => {}
+            }
+        }
+        let wanted = |contact: &SpatialEntry| {
+            contact.domain == Domain::Air
+                && factions.hostile(faction, contact.faction)
+                && cover.is_none_or(|cover| contact.position.distance(cover) < COVER_RANGE)
+        };
+
+        let current = ai
+            .target
+            .and_then(|target| contacts.get(target))
+            .filter(|current| wanted(current));
+
+        // stagger re-evaluation so the whole flight doesn't switch on the same tick. Aircraft
+        // thinking less often re-evaluate on whichever of their decisions falls in the window.
+        let due = (clock.tick + id.0 as u64) % REEVALUATE_TICKS < lod.level.interval();
+        if current.is_some() && !due {
+            return;
+        }
+
+        let current = current.map(|current| {
+            let others = attacking(faction, current.entity).saturating_sub(1);
+            (
+                current.entity,
+                score(&weights, position, ai.velocity, current, others),
+            )
+        });
+
+        let best = contacts
+            .iter()
+            // an RWR bearing isn't enough to go after
+            .filter(|track| track.sensor.precise())
+            .map(|track| &track.entry)
+            .filter(|contact| wanted(contact))
+            .map(|contact| {
+                let mut others = attacking(faction, contact.entity);
+                if Some(contact.entity) == ai.target {
+                    others = others.saturating_sub(1);
+                }
+                (
+                    contact.entity,
+                    score(&weights, position, ai.velocity, contact, others),
+                )
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b));
+
+        ai.target = choose(&weights, current, best);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contact(position: Vec3, forward: Vec3) -> SpatialEntry {
+        SpatialEntry {
+            entity: Entity::from_raw(1),
+            id: SimId(1),
+            faction: Faction(1),
+            domain: Domain::Air,
+            position,
+            forward,
+            velocity: forward * 5.0,
+            health: 100.0,
+            radar: None,
+        }
+    }
+
+    fn score_of(contact: &SpatialEntry, attackers: u32) -> f32 {
+        let weights = TargetWeights::default();
+        score(&weights, Vec3::ZERO, -Vec3::Z * 5.0, contact, attackers)
+    }
+
+    #[test]
+    fn tails_score_above_noses() {
+        let ahead = -Vec3::Z * 2000.0;
+        let tail = score_of(&contact(ahead, -Vec3::Z), 0);
+        let beam = score_of(&contact(ahead, Vec3::X), 0);
+        let nose = score_of(&contact(ahead, Vec3::Z), 0);
+        assert!(tail > beam, "{} {}", tail, beam);
+
+        // one pointing at us from close in is a threat, which outweighs its aspect
+        assert!(nose > beam, "{} {}", nose, beam);
+        let far = score_of(&contact(ahead * 2.0, Vec3::Z), 0);
+        let far_beam = score_of(&contact(ahead * 2.0, Vec3::X), 0);
+        assert!(far < far_beam, "{} {}", far, far_beam);
+    }
+
+    #[test]
+    fn closer_damaged_and_unclaimed_targets_score_higher() {
+        let near = contact(-Vec3::Z * 1000.0, Vec3::X);
+        let far = contact(-Vec3::Z * 6000.0, Vec3::X);
+        assert!(score_of(&near, 0) > score_of(&far, 0));
+
+        let damaged = SpatialEntry {
+            health: 40.0,
+            ..near
+        };
+        assert!(score_of(&damaged, 0) > score_of(&near, 0));
+
+        let weights = TargetWeights::default();
+        let penalty = score_of(&near, 0) - score_of(&near, 2);
+        assert!((penalty - 2.0 * weights.allocation).abs() < 1e-5);
+    }
+
+    #[test]
+    fn switching_needs_a_clear_margin() {
+        let weights = TargetWeights::default();
+        let (current, other) = (Entity::from_raw(1), Entity::from_raw(2));
+
+        let slightly = Some((other, 0.5 + weights.hysteresis * 0.5));
+        assert_eq!(
+            choose(&weights, Some((current, 0.5)), slightly),
+            Some(current)
+        );
+        let clearly = Some((other, 0.5 + weights.hysteresis * 1.5));
+        assert_eq!(choose(&weights, Some((current, 0.5)), clearly), Some(other));
+
+        assert_eq!(choose(&weights, None, slightly), Some(other));
+        assert_eq!(choose(&weights, Some((current, 0.5)), None), Some(current));
+        assert_eq!(choose(&weights, None, None), None);
+    }
+}
@@ -0,0 +1,369 @@
+use std::fs;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::bfm::Manoeuvre;
+use super::state::AIMode;
+use super::tactics::Tactic;
+use crate::recording::Recorder;
+
+const BEHAVIOUR_PATH: &str = "assets/config/ai_behaviour.ron";
+/// used when the behaviour file can't be read, so a broken edit doesn't leave the AI brainless
+const BUILT_IN_BEHAVIOUR: &str = include_str!("../../assets/config/ai_behaviour.ron");
+
+/// Something an AI knows about its situation, as a number, for picking its mode
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
+pub enum Factor {
+    /// range to the current target, or infinite without one
+    TargetRange,
+    /// range to the closest hostile, or infinite if none are near
+    NearestHostileRange,
+    Health,
+    /// fraction of a full tank
+    Fuel,
+    /// 1 when a hostile is on our tail and the pilot has reacted to it
+    ThreatInView,
+    /// 1 within refuelling distance of home
+    AtBase,
+    /// 1 when the flight lead has sent us home
+    OrderedHome,
+    /// the pilot's aggression, from 0 to 1
+    Aggression,
+    TicksInMode,
+    /// 1 while already in the given mode
+    InMode(AIMode),
+    /// 1 while intercepting, engaging or evading
+    Committed,
+}
+
+/// The geometry of a fight, as a number, for picking a manoeuvre. Angles are in radians.
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
+pub enum ManoeuvreFactor {
+    Range,
+    /// between our nose and the line of sight to the opponent
+    AntennaTrain,
+    /// between the opponent's nose and the line of sight to us, 0 when it's pointing at us
+    ThreatAngle,
+    /// between our heading and the opponent's
+    AngleOff,
+    /// rate the range is closing at, in units per tick
+    Closure,
+    /// our specific energy less the opponent's, as a fraction of ours
+    EnergyAdvantage,
+    HeightAboveGround,
+}
+
+/// What a flight can see of the hostiles around it, as a number, for picking a tactic
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
+pub enum TacticFactor {
+    /// to the middle of the hostiles
+    Range,
+    Hostiles,
+    /// hostiles pointing at the flight
+    Threats,
+    Members,
+    /// hostiles less members, positive when outnumbered
+    Odds,
+    /// of the members, out of 100
+    AverageHealth,
+}
+
+/// Maps an input onto a score between 0 and 1
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum Curve {
+    /// 1 at or above the threshold, 0 below it
+    Step(f32),
+    /// 1 below the threshold, 0 at or above it
+    Below(f32),
+    /// 0 at the first value, 1 at the second, straight in between and clamped outside
+    Linear(f32, f32),
+}
+
+impl Curve {
+    pub fn score(self, x: f32) -> f32 {
+        match self {
+            Curve::Step(threshold) => (x >= threshold) as u8 as f32,
+            Curve::Below(threshold) => (x < threshold) as u8 as f32,
+            Curve::Linear(from, to) => {
+                if from == to {
+                    (x >= to) as u8 as f32
+                } else {
+                    ((x - from) / (to - from)).clamp(0.0, 1.0)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Consideration<F> {
+    pub input: F,
+    pub curve: Curve,
+    /// how far the pilot's aggression stretches the input: it's divided by
+    /// `1 + aggression * (pilot's aggression - 0.5)`, so a positive value makes aggressive
+    /// pilots treat things as closer or smaller than they are
+    #[serde(default)]
+    pub aggression: f32,
+}
+
+/// One reason to pick a choice; a choice may have several
+#[derive(Deserialize)]
+pub struct UtilityOption<T, F> {
+    pub choice: T,
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+    /// all multiplied together, so any one scoring 0 rules the option out
+    pub considerations: Vec<Consideration<F>>,
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+/// Utility scoring of a set of choices, in `T`, from what an AI knows, in `F`
+#[derive(Deserialize)]
+pub struct Utility<T, F> {
+    pub options: Vec<UtilityOption<T, F>>,
+    /// bonus to the current choice's score, to stop the AI dithering between close options
+    #[serde(default)]
+    pub inertia: f32,
+}
+
+impl<T: Copy + PartialEq, F: Copy> Utility<T, F> {
+    /// The highest scoring choice; `input` supplies the value of each factor for this AI
+    pub fn choose(&self, current: T, aggression: f32, input: impl Fn(F) -> f32) -> T {
+        // with nothing scoring at all, carry on as we are
+        let mut best = (current, 0.0);
+
+        for option in &self.options {
+            let mut score = option.weight;
+            for consideration in &option.considerations {
+                let stretch = 1.0 + consideration.aggression * (aggression - 0.5);
+                score *= consideration
+                    .curve
+                    .score(input(consideration.input) / stretch.max(0.01));
+                if score == 0.0 {
+                    break;
+                }
+            }
+            if option.choice == current && score > 0.0 {
+                score += self.inertia;
+            }
+
+            if score > best.1 {
+                best = (option.choice, score);
+            }
+        }
+
+        best.0
+    }
+}
+
+/// Every decision the AI makes from data: its mode, the manoeuvre it flies in a fight, and its
+/// flight's tactic
+#[derive(Deserialize)]
+pub struct UtilityBehaviour {
+    pub modes: Utility<AIMode, Factor>,
+    pub manoeuvres: Utility<Manoeuvre, ManoeuvreFactor>,
+    pub tactics: Utility<Tactic, TacticFactor>,
+}
+
+impl UtilityBehaviour {
+    fn parse(contents: &str) -> Result<Self, String> {
+        ron::from_str(contents).map_err(|err| err.to_string())
+    }
+
+    pub fn load() -> Self {
+        match fs::read_to_string(BEHAVIOUR_PATH)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| UtilityBehaviour::parse(&contents))
+        {
+            Ok(behaviour) => behaviour,
+            Err(err) => {
+                error!(
+                    "Could not load {}, using the built in behaviour: {}",
+                    BEHAVIOUR_PATH, err
+                );
+                UtilityBehaviour::parse(BUILT_IN_BEHAVIOUR).expect("built in behaviour is valid")
+            }
+        }
+    }
+}
+
+/// F9 reloads the behaviour file, so it can be tuned while the sim runs. Not while recording or
+/// replaying though, as the replay wouldn't know to change it at the same tick.
+pub fn behaviour_reload_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    recorder: Res<Recorder>,
+    mut behaviour: ResMut<UtilityBehaviour>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F9) {
+        if !matches!(*recorder, Recorder::Idle) {
+            warn!(
+                "Not reloading {} while recording or replaying",
+                BEHAVIOUR_PATH
+            );
+            return;
+        }
+
+        match fs::read_to_string(BEHAVIOUR_PATH)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| UtilityBehaviour::parse(&contents))
+        {
+            Ok(reloaded) => {
+                *behaviour = reloaded;
+                info!("Reloaded {}", BEHAVIOUR_PATH);
+            }
+            Err(err) => error!("Could not reload {}: {}", BEHAVIOUR_PATH, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::bfm::Geometry;
+
+    fn built_in() -> UtilityBehaviour {
+        UtilityBehaviour::parse(BUILT_IN_BEHAVIOUR).unwrap()
+    }
+
+    /// a fight against an opponent straight ahead, on our level and heading, going our speed
+    fn geometry() -> Geometry {
+        Geometry {
+            range: 2000.0,
+            antenna_train: 0.0,
+            threat_angle: std::f32::consts::PI,
+            angle_off: 0.0,
+            closure: 0.0,
+            energy_advantage: 0.0,
+            height_above_ground: 3000.0,
+        }
+    }
+
+    fn manoeuvre(geometry: &Geometry) -> Manoeuvre {
+        built_in()
+            .manoeuvres
+            .choose(Manoeuvre::PurePursuit, 0.5, |factor| {
+                geometry.factor(factor)
+            })
+    }
+
+    #[test]
+    fn inertia_holds_the_current_choice() {
+        let mut behaviour = built_in();
+        behaviour.tactics.inertia = 10.0;
+        let beyond_pincer = |factor| match factor {
+            TacticFactor::Range => 9000.0,
+            TacticFactor::Members => 4.0,
+            TacticFactor::AverageHealth => 100.0,
+            _ => 0.0,
+        };
+        assert_eq!(
+            behaviour
+                .tactics
+                .choose(Tactic::Bracket, 0.5, beyond_pincer),
+            Tactic::Bracket
+        );
+        behaviour.tactics.inertia = 0.0;
+        assert_eq!(
+            behaviour
+                .tactics
+                .choose(Tactic::Bracket, 0.5, beyond_pincer),
+            Tactic::Pincer
+        );
+    }
+
+    #[test]
+    fn manoeuvres_follow_the_geometry() {
+        let ahead = geometry();
+        assert_eq!(manoeuvre(&ahead), Manoeuvre::PurePursuit);
+        assert_eq!(
+            manoeuvre(&Geometry {
+                range: 500.0,
+                ..geometry()
+            }),
+            Manoeuvre::LeadPursuit
+        );
+        assert_eq!(
+            manoeuvre(&Geometry {
+                closure: -10.0,
+                ..geometry()
+            }),
+            Manoeuvre::LowYoYo
+        );
+        assert_eq!(
+            manoeuvre(&Geometry {
+                closure: 10.0,
+                angle_off: 0.8,
+                ..geometry()
+            }),
+            Manoeuvre::HighYoYo
+        );
+
+        // on our tail and pointing at us
+        let behind = Geometry {
+            antenna_train: 3.0,
+            threat_angle: 0.1,
+            ..geometry()
+        };
+        assert_eq!(
+            manoeuvre(&Geometry {
+                range: 300.0,
+                closure: 10.0,
+                ..behind
+            }),
+            Manoeuvre::BarrelRollDefence
+        );
+        assert_eq!(
+            manoeuvre(&Geometry {
+                range: 300.0,
+                closure: 2.0,
+                ..behind
+            }),
+            Manoeuvre::Scissors
+        );
+        assert_eq!(manoeuvre(&behind), Manoeuvre::BreakTurn);
+
+        // just passed head on
+        let passed = Geometry {
+            antenna_train: 1.5,
+            angle_off: 3.0,
+            ..geometry()
+        };
+        assert_eq!(manoeuvre(&passed), Manoeuvre::Immelmann);
+        assert_eq!(
+            manoeuvre(&Geometry {
+                energy_advantage: -0.2,
+                height_above_ground: 500.0,
+                ..passed
+            }),
+            Manoeuvre::LagPursuit
+        );
+    }
+
+    #[test]
+    fn tactics_follow_the_picture() {
+        let tactic = |range: f32, hostiles: f32, threats: f32, members: f32, health: f32| {
+            built_in()
+                .tactics
+                .choose(Tactic::Patrol, 0.5, |factor| match factor {
+                    TacticFactor::Range => range,
+                    TacticFactor::Hostiles => hostiles,
+                    TacticFactor::Threats => threats,
+                    TacticFactor::Members => members,
+                    TacticFactor::Odds => hostiles - members,
+                    TacticFactor::AverageHealth => health,
+                })
+        };
+
+        assert_eq!(tactic(9000.0, 2.0, 0.0, 4.0, 100.0), Tactic::Pincer);
+        assert_eq!(tactic(6000.0, 2.0, 0.0, 4.0, 100.0), Tactic::Bracket);
+        assert_eq!(tactic(4000.0, 2.0, 0.0, 4.0, 100.0), Tactic::Sort);
+        assert_eq!(tactic(9000.0, 6.0, 1.0, 4.0, 100.0), Tactic::Beam);
+        assert_eq!(tactic(9000.0, 6.0, 0.0, 4.0, 100.0), Tactic::Grinder);
+        assert_eq!(tactic(9000.0, 1.0, 0.0, 1.0, 100.0), Tactic::Sort);
+        assert_eq!(tactic(6000.0, 2.0, 0.0, 4.0, 40.0), Tactic::Drag);
+    }
+}
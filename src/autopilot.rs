@@ -0,0 +1,324 @@
+use bevy::prelude::*;
+
+use super::ai::orders::{self, RadioMenu};
+use super::plane::{PilotInput, PlaneSystem, MAXIMUM_THRUST};
+use super::sim_time::{SimTick, TIME_STEP};
+use super::utils::{self, Pid};
+use super::{Controls, Player};
+
+const MAX_BANK: f32 = 0.5;
+const MAX_PITCH: f32 = 0.3;
+const MAX_VERTICAL_SPEED: f32 = 5.0;
+/// distance at which a waypoint counts as reached
+const WAYPOINT_RADIUS: f32 = 200.0;
+/// how far ahead the guidance point is placed for aircraft that steer towards a point
+const GUIDANCE_DISTANCE: f32 = 1000.0;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LateralMode {
+    Off,
+    WingLeveler,
+    /// compass heading in radians
+    Heading(f32),
+    /// follow `Autopilot::waypoints`
+    Navigation,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum VerticalMode {
+    Off,
+    Altitude(f32),
+    VerticalSpeed(f32),
+}
+
+/// Output of one autopilot update; `None` leaves that control to the pilot
+#[derive(Default)]
+pub struct AutopilotCommands {
+    pub pitch: Option<f32>,
+    pub roll: Option<f32>,
+    pub yaw: Option<f32>,
+    pub thrust: Option<f32>,
+}
+
+#[derive(Component)]
+pub struct Autopilot {
+    pub lateral: LateralMode,
+    pub vertical: VerticalMode,
+    /// airspeed to hold, if the autothrottle is engaged
+    pub autothrottle: Option<f32>,
+    pub waypoints: Vec<Vec3>,
+    pub active_waypoint: usize,
+    heading_pid: Pid,
+    bank_pid: Pid,
+    altitude_pid: Pid,
+    vertical_speed_pid: Pid,
+    pitch_pid: Pid,
+    speed_pid: Pid,
+}
+
+impl Default for Autopilot {
+    fn default() -> Self {
+        Autopilot {
+            lateral: LateralMode::Off,
+            vertical: VerticalMode::Off,
+            autothrottle: None,
+            waypoints: Vec::new(),
+            active_waypoint: 0,
+            heading_pid: Pid::new(1.0, 0.0, 0.0, MAX_BANK),
+            bank_pid: Pid::new(2.0, 0.0, 0.2, 1.0),
+            altitude_pid: Pid::new(0.02, 0.0, 0.0, MAX_VERTICAL_SPEED),
+            vertical_speed_pid: Pid::new(0.05, 0.01, 0.0, MAX_PITCH),
+            pitch_pid: Pid::new(3.0, 0.0, 0.3, 1.0),
+            // thrust is trimmed either way from half throttle
+            speed_pid: Pid::new(5.0, 1.0, 0.0, MAXIMUM_THRUST / 2.0),
+        }
+    }
+}
+
+impl Autopilot {
+    pub fn with_waypoints(waypoints: Vec<Vec3>) -> Self {
+        Autopilot {
+            waypoints,
+            ..default()
+        }
+    }
+
+    pub fn engaged(&self) -> bool {
+        self.lateral != LateralMode::Off
+            || self.vertical != VerticalMode::Off
+            || self.autothrottle.is_some()
+    }
+
+    pub fn set_lateral(&mut self, mode: LateralMode) {
+        self.lateral = mode;
+        self.heading_pid.reset();
+        self.bank_pid.reset();
+    }
+
+    pub fn set_vertical(&mut self, mode: VerticalMode) {
+        self.vertical = mode;
+        self.altitude_pid.reset();
+        self.vertical_speed_pid.reset();
+        self.pitch_pid.reset();
+    }
+
+    pub fn set_autothrottle(&mut self, speed: Option<f32>) {
+        self.autothrottle = speed;
+        self.speed_pid.reset();
+    }
+
+    pub fn disengage(&mut self) {
+        self.set_lateral(LateralMode::Off);
+        self.set_vertical(VerticalMode::Off);
+        self.set_autothrottle(None);
+    }
+
+    /// heading to hold, advancing to the next waypoint once the current one is reached
+    fn target_heading(&mut self, position: Vec3, current: f32) -> Option<f32> {
+        match self.lateral {
+            LateralMode::Off => None,
+            LateralMode::WingLeveler => Some(current),
+            LateralMode::Heading(heading) => Some(heading),
+            LateralMode::Navigation => {
+                if self.waypoints.is_empty() {
+                    return Some(current);
+                }
+
+                let mut offset = self.waypoints[self.active_waypoint] - position;
+                if Vec3::new(offset.x, 0.0, offset.z).length() < WAYPOINT_RADIUS {
+                    self.active_waypoint = (self.active_waypoint + 1) % self.waypoints.len();
+                    offset = self.waypoints[self.active_waypoint] - position;
+                }
+
+                Some(utils::heading(offset))
+            }
+        }
+    }
+
+    /// Control surface and throttle commands for aircraft using the full flight model
+    pub fn fly(&mut self, transform: &Transform, velocity: Vec3) -> AutopilotCommands {
+        let mut commands = AutopilotCommands::default();
+
+        let forward = transform.forward();
+        let heading = utils::heading(forward);
+        // positive when banked right
+        let bank = utils::clamp(-transform.local_x().y, -1.0, 1.0).asin();
+        let pitch = utils::clamp(forward.y, -1.0, 1.0).asin();
+
+        if self.lateral != LateralMode::Off {
+            let target_bank = match self.lateral {
+                LateralMode::WingLeveler => 0.0,
+                _ => {
+                    let target = self
+                        .target_heading(transform.translation, heading)
+                        .unwrap_or(heading);
+                    let error = utils::wrap_angle(target - heading);
+                    utils::clamp(
+                        self.heading_pid.update(error, TIME_STEP),
+                        -MAX_BANK,
+                        MAX_BANK,
+                    )
+                }
+            };
+
+            // a positive roll command rolls left
+            let roll = -self.bank_pid.update(target_bank - bank, TIME_STEP);
+            commands.roll = Some(utils::clamp(roll, -1.0, 1.0));
+            commands.yaw = Some(0.0);
+        }
+
+        if self.vertical != VerticalMode::Off {
+            let target_vertical_speed = match self.vertical {
+                VerticalMode::Altitude(altitude) => utils::clamp(
+                    self.altitude_pid
+                        .update(altitude - transform.translation.y, TIME_STEP),
+                    -MAX_VERTICAL_SPEED,
+                    MAX_VERTICAL_SPEED,
+                ),
+                VerticalMode::VerticalSpeed(speed) => speed,
+                VerticalMode::Off => 0.0,
+            };
+
+            let target_pitch = utils::clamp(
+                self.vertical_speed_pid
+                    .update(target_vertical_speed - velocity.y, TIME_STEP),
+                -MAX_PITCH,
+                MAX_PITCH,
+            );
+
+            let pitch = self.pitch_pid.update(target_pitch - pitch, TIME_STEP);
+            commands.pitch = Some(utils::clamp(pitch, -1.0, 1.0));
+        }
+
+        if let Some(speed) = self.autothrottle {
+            let thrust =
+                MAXIMUM_THRUST / 2.0 + self.speed_pid.update(speed - velocity.length(), TIME_STEP);
+            commands.thrust = Some(utils::clamp(thrust, 0.0, MAXIMUM_THRUST));
+        }
+
+        commands
+    }
+
+    /// A point to steer towards, for aircraft that fly by pointing at a position
+    pub fn guidance_point(&mut self, transform: &Transform, velocity: Vec3) -> Option<Vec3> {
+        if self.lateral == LateralMode::Off && self.vertical == VerticalMode::Off {
+            return None;
+        }
+
+        let position = transform.translation;
+        let current = utils::heading(transform.forward());
+        let heading = self.target_heading(position, current).unwrap_or(current);
+        let horizontal = Vec3::new(heading.sin(), 0.0, -heading.cos());
+
+        let climb = match self.vertical {
+            VerticalMode::Off => 0.0,
+            VerticalMode::Altitude(altitude) => altitude - position.y,
+            VerticalMode::VerticalSpeed(speed) => {
+                speed * GUIDANCE_DISTANCE / velocity.length().max(1.0)
+            }
+        };
+        let climb = utils::clamp(climb, -GUIDANCE_DISTANCE * 0.3, GUIDANCE_DISTANCE * 0.3);
+
+        Some(position + horizontal * GUIDANCE_DISTANCE + Vec3::new(0.0, climb, 0.0))
+    }
+}
+
+pub struct AutopilotPlugin;
+
+impl Plugin for AutopilotPlugin {
+    fn build(&self, app: &mut App) {
+        // before the radio menu closes, so the key that picks an order isn't also read here
+        app.add_system(autopilot_keys_system.before(orders::radio_system))
+            .add_system_set(
+                SystemSet::new().with_run_criteria(SimTick).with_system(
+                    player_autopilot_system
+                        .label(PlaneSystem::Autopilot)
+                        .after(PlaneSystem::Controls)
+                        .before(PlaneSystem::Flight),
+                ),
+            );
+    }
+}
+
+/// A circuit around the city for the navigation mode
+pub fn city_circuit() -> Vec<Vec3> {
+    vec![
+        Vec3::new(3000.0, 1000.0, 0.0),
+        Vec3::new(3000.0, 1000.0, 3000.0),
+        Vec3::new(-3000.0, 1000.0, 3000.0),
+        Vec3::new(-3000.0, 1000.0, -3000.0),
+    ]
+}
+
+fn autopilot_keys_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    radio_menu: Res<RadioMenu>,
+    mut query: Query<(&Transform, &Player, &mut Autopilot)>,
+) {
+    // the number keys pick radio calls while the menu is open
+    if radio_menu.open {
+        return;
+    }
+
+    for (transform, player, mut autopilot) in query.iter_mut() {
+        let heading = utils::heading(transform.forward());
+
+        if keyboard_input.just_pressed(KeyCode::Key0) {
+            autopilot.disengage();
+        }
+        if keyboard_input.just_pressed(KeyCode::Key1) {
+            autopilot.set_lateral(LateralMode::WingLeveler);
+        }
+        if keyboard_input.just_pressed(KeyCode::Key2) {
+            autopilot.set_lateral(LateralMode::Heading(heading));
+        }
+        if keyboard_input.just_pressed(KeyCode::Key3) {
+            autopilot.set_lateral(LateralMode::Navigation);
+        }
+        if keyboard_input.just_pressed(KeyCode::Key4) {
+            autopilot.set_vertical(VerticalMode::Altitude(transform.translation.y));
+        }
+        if keyboard_input.just_pressed(KeyCode::Key5) {
+            autopilot.set_vertical(VerticalMode::VerticalSpeed(player.velocity.y));
+        }
+        if keyboard_input.just_pressed(KeyCode::Key6) {
+            let speed = match autopilot.autothrottle {
+                Some(_) => None,
+                None => Some(player.velocity.length()),
+            };
+            autopilot.set_autothrottle(speed);
+        }
+    }
+}
+
+fn player_autopilot_system(
+    pilot_input: Res<PilotInput>,
+    mut controls: ResMut<Controls>,
+    mut query: Query<(&Transform, &Player, &mut Autopilot)>,
+) {
+    for (transform, player, mut autopilot) in query.iter_mut() {
+        // the pilot taking the stick or throttle hands that axis back to them
+        if pilot_input.attitude {
+            autopilot.set_lateral(LateralMode::Off);
+            autopilot.set_vertical(VerticalMode::Off);
+        }
+        if pilot_input.throttle {
+            autopilot.set_autothrottle(None);
+        }
+
+        let commands = autopilot.fly(transform, player.velocity);
+
+        if let Some(pitch) = commands.pitch {
+            controls.pitch = pitch;
+        }
+        if let Some(roll) = commands.roll {
+            controls.roll = roll;
+        }
+        if let Some(yaw) = commands.yaw {
+            controls.yaw = yaw;
+        }
+        if let Some(thrust) = commands.thrust {
+            controls.thrust = thrust;
+        }
+    }
+}
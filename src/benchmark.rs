@@ -0,0 +1,147 @@
+use std::time::{Duration, Instant};
+
+use bevy::{app::AppExit, log::LogPlugin, prelude::*, tasks::ComputeTaskPool};
+
+use super::ai::{
+    self,
+    lod::{Lod, LodFocus, LodLevel},
+    sensors::CloudLayer,
+    skill::SkillProfiles,
+    tactics::Flight,
+    targeting::TargetWeights,
+    utility::UtilityBehaviour,
+};
+use super::faction::{Faction, Factions};
+use super::sim_time::{SimClock, SimIds, TIME_STEP};
+use super::spatial::{self, SpatialIndex, SpatialIndexUpdate};
+use super::terrain::HeightMap;
+
+/// battle sizes flown, one after the other
+const SCENARIOS: [usize; 3] = [100, 1000, 5000];
+/// ticks flown before measuring, so the first contacts and tactic changes are out of the way
+const WARM_UP_TICKS: u32 = 60;
+const MEASURED_TICKS: u32 = 180;
+
+/// `cargo run --release -- --bench-ai` flies battles of increasing size without a window
+/// and logs what an AI tick costs at each
+pub fn requested() -> bool {
+    std::env::args().any(|arg| arg == "--bench-ai")
+}
+
+pub fn run() {
+    App::new()
+        .add_plugins(MinimalPlugins)
+        .add_plugin(LogPlugin)
+        // two sides that are hostile to each other, so everyone has someone to fight
+        .insert_resource(Factions::default())
+        .insert_resource(SkillProfiles::load())
+        .insert_resource(UtilityBehaviour::load())
+        .init_resource::<TargetWeights>()
+        .init_resource::<CloudLayer>()
+        .init_resource::<SpatialIndex>()
+        .init_resource::<HeightMap>()
+        .init_resource::<SimClock>()
+        .init_resource::<Benchmark>()
+        .add_startup_system(setup)
+        .add_system_to_stage(CoreStage::PreUpdate, scenario_system)
+        .add_system(spatial::spatial_index_system.label(SpatialIndexUpdate))
+        .add_system_set(ai::ai_systems())
+        .add_system_to_stage(CoreStage::PostUpdate, measure_system)
+        .run();
+}
+
+/// stands in for the player in the middle of the battle, for the level of detail to work around
+fn setup(mut commands: Commands) {
+    commands
+        .spawn_bundle((Transform::identity(), GlobalTransform::identity()))
+        .insert(LodFocus);
+}
+
+#[derive(Default)]
+struct Benchmark {
+    scenario: usize,
+    tick: u32,
+    started: Option<Instant>,
+    measured: Vec<Duration>,
+}
+
+/// Spawns each scenario's battle in place of the last, and starts the clock on every tick
+#[allow(clippy::type_complexity)]
+fn scenario_system(
+    mut commands: Commands,
+    mut benchmark: ResMut<Benchmark>,
+    mut clock: ResMut<SimClock>,
+    factions: Res<Factions>,
+    profiles: Res<SkillProfiles>,
+    pool: Res<ComputeTaskPool>,
+    old: Query<Entity, Or<(With<Faction>, With<Flight>)>>,
+) {
+    if benchmark.tick == 0 {
+        for entity in old.iter() {
+            commands.entity(entity).despawn();
+        }
+
+        let aircraft = SCENARIOS[benchmark.scenario];
+        info!(
+            "Flying {} aircraft for {} ticks on {} threads",
+            aircraft,
+            MEASURED_TICKS,
+            pool.thread_num()
+        );
+        ai::spawn_factions(
+            &mut commands,
+            &mut SimIds::default(),
+            &factions,
+            &profiles,
+            Some(aircraft),
+            None,
+            None,
+        );
+    }
+
+    clock.tick += 1;
+    benchmark.started = Some(Instant::now());
+}
+
+fn measure_system(
+    mut benchmark: ResMut<Benchmark>,
+    mut exit: EventWriter<AppExit>,
+    lods: Query<&Lod>,
+) {
+    let elapsed = benchmark
+        .started
+        .map_or(Duration::ZERO, |started| started.elapsed());
+    benchmark.tick += 1;
+    if benchmark.tick <= WARM_UP_TICKS {
+        return;
+    }
+    benchmark.measured.push(elapsed);
+    if benchmark.tick < WARM_UP_TICKS + MEASURED_TICKS {
+        return;
+    }
+
+    let to_ms = |duration: Duration| duration.as_secs_f32() * 1000.0;
+    let mean = to_ms(benchmark.measured.iter().sum()) / benchmark.measured.len() as f32;
+    let worst = benchmark.measured.iter().copied().max().map_or(0.0, to_ms);
+    let budget = TIME_STEP * 1000.0;
+    let at = |level| lods.iter().filter(|lod| lod.level == level).count();
+    info!(
+        "{} aircraft: {:.2} ms mean, {:.2} ms worst per tick, {:.0}% of the {:.1} ms tick \
+        ({} full, {} reduced, {} distant detail)",
+        SCENARIOS[benchmark.scenario],
+        mean,
+        worst,
+        mean / budget * 100.0,
+        budget,
+        at(LodLevel::Full),
+        at(LodLevel::Reduced),
+        at(LodLevel::Distant)
+    );
+
+    benchmark.scenario += 1;
+    benchmark.tick = 0;
+    benchmark.measured.clear();
+    if benchmark.scenario == SCENARIOS.len() {
+        exit.send(AppExit);
+    }
+}
@@ -0,0 +1,447 @@
+use bevy::prelude::*;
+use rand::Rng;
+
+use super::ai::{
+    bfm::Bfm,
+    lod::Lod,
+    orders::WingmanOrder,
+    sensors::{Contacts, Radar},
+    skill::Skill,
+    state::{AIState, HomeBase},
+    tactics::FlightMember,
+};
+use super::autopilot::Autopilot;
+use super::faction::{Faction, Factions};
+use super::formation::{Formation, Wingman};
+use super::gun::Gun;
+use super::missile::MissileRack;
+use super::mission::RestartMission;
+use super::plane::{GRAVITY, PLANE_MODEL};
+use super::sim_time::{SimRng, SimRngReseed, SimTick};
+use super::terrain::HeightMap;
+use super::{Player, AI};
+
+pub const ZONE_COUNT: usize = 5;
+/// fraction of a full tank lost per tick with the tanks shot through
+const FUEL_LEAK: f32 = 0.0005;
+const FIRE_TICKS: u32 = 90;
+const BREAKUP_TICKS: u32 = 45;
+/// pieces thrown off when the airframe breaks up
+const DEBRIS: usize = 4;
+/// speed debris is thrown off at, in units per tick
+const DEBRIS_SPEED: f32 = 4.0;
+/// radians per tick a wreck tumbles at once it's broken up
+const TUMBLE_RATE: f32 = 0.08;
+/// fraction of speed lost per tick once the airframe has stopped flying
+const WRECK_DRAG: f32 = 0.01;
+
+/// Applies the tick's hits, after every weapon has fired and before anything reacts to losses
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DamageUpdate;
+
+/// The weapons that hit things, in the order they fly, so the tick's hits come in the same
+/// order every run
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum WeaponSystem {
+    Guns,
+    Missiles,
+    Ground,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Zone {
+    Engine,
+    Wings,
+    Tail,
+    Cockpit,
+    Fuel,
+}
+
+impl Zone {
+    pub const ALL: [Zone; ZONE_COUNT] = [
+        Zone::Engine,
+        Zone::Wings,
+        Zone::Tail,
+        Zone::Cockpit,
+        Zone::Fuel,
+    ];
+
+    /// damage that knocks it out
+    fn strength(self) -> f32 {
+        match self {
+            Zone::Engine => 40.0,
+            Zone::Wings => 60.0,
+            Zone::Tail => 35.0,
+            Zone::Cockpit => 25.0,
+            Zone::Fuel => 45.0,
+        }
+    }
+
+    /// which zone a point on the airframe, in the aircraft's own frame, falls in
+    pub fn at(local: Vec3) -> Zone {
+        // the nose points along -z
+        if local.x.abs() > 3.0 {
+            Zone::Wings
+        } else if local.z < -5.0 {
+            Zone::Cockpit
+        } else if local.z > 6.0 {
+            Zone::Tail
+        } else if local.z > 1.0 {
+            Zone::Engine
+        } else {
+            Zone::Fuel
+        }
+    }
+}
+
+/// Something hit an aircraft, ground unit or ship
+pub struct Hit {
+    pub entity: Entity,
+    /// where it hit an aircraft, or None for a blast that catches the whole airframe or a hit on
+    /// anything else
+    pub zone: Option<Zone>,
+    pub damage: f32,
+}
+
+/// An aircraft has been shot down and no longer fights; anything holding on to it should let go
+pub struct AircraftDestroyed {
+    pub entity: Entity,
+}
+
+/// A ground unit or ship has been destroyed and removed
+pub struct SurfaceDestroyed {
+    pub entity: Entity,
+}
+
+/// What's left of each part of an airframe, 1 intact and 0 knocked out
+#[derive(Component)]
+pub struct Damage {
+    integrity: [f32; ZONE_COUNT],
+}
+
+impl Default for Damage {
+    fn default() -> Self {
+        Damage {
+            integrity: [1.0; ZONE_COUNT],
+        }
+    }
+}
+
+impl Damage {
+    pub fn integrity(&self, zone: Zone) -> f32 {
+        self.integrity[zone as usize]
+    }
+
+    /// 100 undamaged, 0 with every part knocked out
+    pub fn health(&self) -> f32 {
+        self.integrity.iter().sum::<f32>() / ZONE_COUNT as f32 * 100.0
+    }
+
+    fn hit(&mut self, zone: Zone, damage: f32) {
+        let integrity = &mut self.integrity[zone as usize];
+        *integrity = (*integrity - damage / zone.strength()).max(0.0);
+    }
+
+    /// losing any of these brings the aircraft down; a dead engine only leaves it gliding
+    fn destroyed(&self) -> bool {
+        [Zone::Wings, Zone::Tail, Zone::Cockpit, Zone::Fuel]
+            .into_iter()
+            .any(|zone| self.integrity(zone) == 0.0)
+    }
+
+    /// fraction of full thrust the engine still gives
+    pub fn thrust(&self) -> f32 {
+        self.integrity(Zone::Engine)
+    }
+
+    /// fraction of full roll authority the wings still give
+    pub fn roll(&self) -> f32 {
+        0.3 + 0.7 * self.integrity(Zone::Wings)
+    }
+
+    /// fraction of full pitch and yaw authority the tail still gives
+    pub fn pitch_yaw(&self) -> f32 {
+        0.3 + 0.7 * self.integrity(Zone::Tail)
+    }
+
+    pub fn lift(&self) -> f32 {
+        0.6 + 0.4 * self.integrity(Zone::Wings)
+    }
+
+    /// fraction of a full tank lost per tick
+    pub fn fuel_leak(&self) -> f32 {
+        (1.0 - self.integrity(Zone::Fuel)) * FUEL_LEAK
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum WreckStage {
+    /// still flying, burning and losing height
+    OnFire,
+    /// coming apart and tumbling
+    BreakingUp,
+    /// falling until it hits the ground
+    Crashing,
+}
+
+/// What's left of a destroyed aircraft, or a piece of one, on its way down
+#[derive(Component)]
+pub struct Wreck {
+    stage: WreckStage,
+    ticks: u32,
+    velocity: Vec3,
+    /// turned through each tick once it's tumbling
+    spin: Quat,
+    /// how fast and which way each piece is thrown off and tumbles when it breaks up, drawn when
+    /// it's shot down
+    debris: Vec<(Vec3, Quat)>,
+}
+
+impl Wreck {
+    fn new(stage: WreckStage, velocity: Vec3, spin: Quat, debris: Vec<(Vec3, Quat)>) -> Self {
+        Wreck {
+            stage,
+            ticks: 0,
+            velocity,
+            spin,
+            debris,
+        }
+    }
+}
+
+struct WreckModels {
+    fire_mesh: Handle<Mesh>,
+    fire_material: Handle<StandardMaterial>,
+    debris_mesh: Handle<Mesh>,
+    debris_material: Handle<StandardMaterial>,
+}
+
+pub struct DamagePlugin;
+
+impl Plugin for DamagePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Hit>()
+            .add_event::<AircraftDestroyed>()
+            .add_event::<SurfaceDestroyed>()
+            .add_startup_system(setup)
+            .add_system_to_stage(CoreStage::PreUpdate, restart_system)
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(SimTick)
+                    .with_system(damage_system.label(DamageUpdate).after(SimRngReseed))
+                    .with_system(wreck_system.after(DamageUpdate)),
+            );
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(WreckModels {
+        fire_mesh: meshes.add(Mesh::from(shape::Icosphere {
+            radius: 4.0,
+            subdivisions: 1,
+        })),
+        fire_material: materials.add(StandardMaterial {
+            base_color: Color::rgb(1.0, 0.45, 0.1),
+            emissive: Color::rgb(1.0, 0.35, 0.05),
+            unlit: true,
+            ..default()
+        }),
+        debris_mesh: meshes.add(Mesh::from(shape::Box::new(3.0, 0.5, 4.0))),
+        debris_material: materials.add(Color::rgb(0.25, 0.25, 0.27).into()),
+    });
+}
+
+/// Clears away the wrecks still falling
+fn restart_system(
+    mut commands: Commands,
+    mut events: EventReader<RestartMission>,
+    wrecks: Query<Entity, With<Wreck>>,
+) {
+    if events.iter().count() == 0 {
+        return;
+    }
+
+    for entity in wrecks.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// A random axis to tumble around
+fn tumble(rng: &mut SimRng) -> Quat {
+    let axis = Vec3::new(
+        rng.rng.gen_range(-1.0..1.0),
+        rng.rng.gen_range(-1.0..1.0),
+        rng.rng.gen_range(-1.0..1.0),
+    )
+    .try_normalize()
+    .unwrap_or(Vec3::X);
+    Quat::from_axis_angle(axis, TUMBLE_RATE)
+}
+
+/// Applies the tick's hits to the zones they struck, and sets light to anything shot down.
+/// The AI stops flying a destroyed aircraft, which becomes a wreck; the player leaves a wreck
+/// behind and is put back in the air.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn damage_system(
+    mut commands: Commands,
+    mut hits: EventReader<Hit>,
+    mut destroyed: EventWriter<AircraftDestroyed>,
+    mut rng: ResMut<SimRng>,
+    asset_server: Res<AssetServer>,
+    models: Res<WreckModels>,
+    factions: Res<Factions>,
+    mut query: Query<(
+        &Transform,
+        &mut Damage,
+        Option<&Faction>,
+        Option<&AI>,
+        Option<&Player>,
+    )>,
+) {
+    for hit in hits.iter() {
+        let (transform, mut damage, faction, ai, player) = match query.get_mut(hit.entity) {
+            Ok(aircraft) => aircraft,
+            Err(_) => continue,
+        };
+        // already on its way down, from an earlier hit this tick
+        if damage.destroyed() {
+            continue;
+        }
+
+        match hit.zone {
+            Some(zone) => damage.hit(zone, hit.damage),
+            None => {
+                for zone in Zone::ALL {
+                    damage.hit(zone, hit.damage);
+                }
+            }
+        }
+        if !damage.destroyed() {
+            continue;
+        }
+
+        info!(
+            "{} aircraft destroyed",
+            faction.map_or("Unknown", |faction| factions.name(*faction))
+        );
+        destroyed.send(AircraftDestroyed { entity: hit.entity });
+
+        let velocity = ai
+            .map(|ai| ai.velocity)
+            .or_else(|| player.map(|player| player.velocity))
+            .unwrap_or(Vec3::ZERO);
+        let spin = tumble(&mut rng);
+        let debris = (0..DEBRIS)
+            .map(|_| {
+                let thrown = Vec3::new(
+                    rng.rng.gen_range(-1.0..1.0),
+                    rng.rng.gen_range(-1.0..1.0),
+                    rng.rng.gen_range(-1.0..1.0),
+                ) * DEBRIS_SPEED;
+                (thrown, tumble(&mut rng))
+            })
+            .collect();
+        let wreck = Wreck::new(WreckStage::OnFire, velocity, spin, debris);
+
+        let mut entity = match player {
+            // the player flies on in a fresh aircraft, so the wreck is a copy of the old one
+            Some(_) => {
+                let mut entity = commands.spawn_bundle((*transform, GlobalTransform::identity()));
+                let scene = asset_server.load(PLANE_MODEL);
+                entity.with_children(|parent| {
+                    // center of the plane is not at 0,0 so offset slightly
+                    parent
+                        .spawn_bundle((
+                            Transform::from_translation(Vec3::new(0.0, -5.0, 0.0))
+                                .with_rotation(Quat::from_rotation_y(-std::f32::consts::FRAC_PI_2)),
+                            GlobalTransform::identity(),
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn_scene(scene);
+                        });
+                });
+                entity
+            }
+            None => {
+                let mut entity = commands.entity(hit.entity);
+                entity
+                    .remove_bundle::<(AI, AIState, Bfm, Lod, Contacts, Skill, HomeBase)>()
+                    .remove_bundle::<(Faction, Radar, Gun, MissileRack, Damage, Autopilot)>()
+                    .remove_bundle::<(Wingman, WingmanOrder, FlightMember, Formation)>();
+                entity
+            }
+        };
+        entity.insert(wreck).with_children(|parent| {
+            parent.spawn_bundle(PbrBundle {
+                mesh: models.fire_mesh.clone(),
+                material: models.fire_material.clone(),
+                transform: Transform::from_xyz(0.0, 0.0, 4.0),
+                ..default()
+            });
+        });
+    }
+}
+
+/// Takes each wreck from burning through breaking up to crashing, and clears it away once it's
+/// hit the ground
+fn wreck_system(
+    mut commands: Commands,
+    height_map: Res<HeightMap>,
+    models: Res<WreckModels>,
+    mut query: Query<(Entity, &mut Transform, &mut Wreck)>,
+) {
+    for (entity, mut transform, mut wreck) in query.iter_mut() {
+        wreck.ticks += 1;
+
+        match wreck.stage {
+            WreckStage::OnFire => {
+                // still flying after a fashion, but the lift is going
+                wreck.velocity.y += GRAVITY * 0.5;
+                let ahead = transform.translation + wreck.velocity;
+                if wreck.velocity != Vec3::ZERO {
+                    transform.look_at(ahead, Vec3::Y);
+                }
+
+                if wreck.ticks >= FIRE_TICKS {
+                    wreck.stage = WreckStage::BreakingUp;
+                    wreck.ticks = 0;
+
+                    for (thrown, spin) in std::mem::take(&mut wreck.debris) {
+                        commands
+                            .spawn_bundle(PbrBundle {
+                                mesh: models.debris_mesh.clone(),
+                                material: models.debris_material.clone(),
+                                transform: *transform,
+                                ..default()
+                            })
+                            .insert(Wreck::new(
+                                WreckStage::Crashing,
+                                wreck.velocity + thrown,
+                                spin,
+                                Vec::new(),
+                            ));
+                    }
+                }
+            }
+            WreckStage::BreakingUp | WreckStage::Crashing => {
+                wreck.velocity.y += GRAVITY;
+                wreck.velocity *= 1.0 - WRECK_DRAG;
+                transform.rotation = wreck.spin * transform.rotation;
+
+                if wreck.stage == WreckStage::BreakingUp && wreck.ticks >= BREAKUP_TICKS {
+                    wreck.stage = WreckStage::Crashing;
+                    wreck.ticks = 0;
+                }
+            }
+        }
+
+        transform.translation += wreck.velocity;
+        if transform.translation.y < height_map.height(transform.translation) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
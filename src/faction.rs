@@ -0,0 +1,142 @@
+use std::fs;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+const FACTIONS_PATH: &str = "assets/config/factions.ron";
+
+/// Which faction an aircraft flies for, an index into `Factions`
+#[derive(Component, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Faction(pub usize);
+
+impl Faction {
+    /// the first faction in the config is the player's
+    pub const PLAYER: Faction = Faction(0);
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+pub enum Relation {
+    Friendly,
+    Neutral,
+    Hostile,
+}
+
+#[derive(Deserialize)]
+pub struct FactionConfig {
+    pub name: String,
+    /// AI aircraft spawned for the faction, unless a stress scenario asks for more
+    pub aircraft: usize,
+}
+
+#[derive(Deserialize)]
+struct FactionsFile {
+    factions: Vec<FactionConfig>,
+    /// between factions not listed in `relations`; a faction is always friendly with itself
+    default_relation: Relation,
+    relations: Vec<(String, String, Relation)>,
+}
+
+/// Every faction and how each feels about the others
+pub struct Factions {
+    pub factions: Vec<FactionConfig>,
+    /// row major, `relations[a * len + b]`
+    relations: Vec<Relation>,
+}
+
+impl Default for Factions {
+    fn default() -> Self {
+        Factions::from_file(FactionsFile {
+            factions: vec![
+                FactionConfig {
+                    name: "Blue".to_string(),
+                    aircraft: 10,
+                },
+                FactionConfig {
+                    name: "Red".to_string(),
+                    aircraft: 10,
+                },
+            ],
+            default_relation: Relation::Hostile,
+            relations: Vec::new(),
+        })
+    }
+}
+
+impl Factions {
+    fn from_file(file: FactionsFile) -> Self {
+        let len = file.factions.len();
+        let mut factions = Factions {
+            relations: vec![file.default_relation; len * len],
+            factions: file.factions,
+        };
+
+        for i in 0..len {
+            factions.set_relation(Faction(i), Faction(i), Relation::Friendly);
+        }
+        for (a, b, relation) in &file.relations {
+            match (factions.find(a), factions.find(b)) {
+                (Some(a), Some(b)) => factions.set_relation(a, b, *relation),
+                _ => warn!("Relation between unknown factions {} and {}", a, b),
+            }
+        }
+
+        factions
+    }
+
+    pub fn load() -> Self {
+        match fs::read_to_string(FACTIONS_PATH)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| ron::from_str(&contents).map_err(|err| err.to_string()))
+        {
+            Ok(file) => Factions::from_file(file),
+            Err(err) => {
+                error!("Could not load {}: {}", FACTIONS_PATH, err);
+                Factions::default()
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.factions.len()
+    }
+
+    pub fn find(&self, name: &str) -> Option<Faction> {
+        self.factions
+            .iter()
+            .position(|faction| faction.name == name)
+            .map(Faction)
+    }
+
+    pub fn name(&self, faction: Faction) -> &str {
+        self.factions
+            .get(faction.0)
+            .map_or("Unknown", |faction| &faction.name)
+    }
+
+    /// relations are mutual, so this sets both ways round
+    pub fn set_relation(&mut self, a: Faction, b: Faction, relation: Relation) {
+        let len = self.len();
+        self.relations[a.0 * len + b.0] = relation;
+        self.relations[b.0 * len + a.0] = relation;
+    }
+
+    pub fn relation(&self, a: Faction, b: Faction) -> Relation {
+        let len = self.len();
+        if a.0 >= len || b.0 >= len {
+            return Relation::Neutral;
+        }
+        self.relations[a.0 * len + b.0]
+    }
+
+    pub fn hostile(&self, a: Faction, b: Faction) -> bool {
+        self.relation(a, b) == Relation::Hostile
+    }
+}
+
+pub struct FactionPlugin;
+
+impl Plugin for FactionPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Factions::load());
+    }
+}
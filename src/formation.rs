@@ -0,0 +1,200 @@
+use bevy::prelude::*;
+
+use super::ai;
+use super::damage::{AircraftDestroyed, DamageUpdate};
+use super::recording::{Recorder, RecorderUpdate};
+use super::sim_time::SimTick;
+use super::spatial::{SpatialEntry, SpatialIndex, SpatialIndexUpdate};
+use super::utils::{self, Pid};
+use super::{Controls, Player};
+
+const DEFAULT_SPACING: f32 = 60.0;
+/// how far ahead of its slot a wingman aims, along the lead's heading
+const STATION_LOOKAHEAD: f32 = 300.0;
+/// wingmen may fly this much slower or faster than the lead to hold their slot
+const MAX_SPEED_ADJUSTMENT: f32 = 0.5;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FormationShape {
+    /// stepped back to the lead's right
+    Echelon,
+    /// finger-four: two on the left, three and four on the right
+    Fingertip,
+    LineAbreast,
+    Trail,
+}
+
+impl FormationShape {
+    pub fn next(self) -> Self {
+        match self {
+            FormationShape::Echelon => FormationShape::Fingertip,
+            FormationShape::Fingertip => FormationShape::LineAbreast,
+            FormationShape::LineAbreast => FormationShape::Trail,
+            FormationShape::Trail => FormationShape::Echelon,
+        }
+    }
+
+    /// Position of a slot in the lead's frame, where +X is the lead's right and +Z is behind it.
+    /// Slot 0 is the lead.
+    pub fn slot_offset(self, slot: usize, spacing: f32) -> Vec3 {
+        let n = slot as f32;
+        // alternate left and right for shapes that spread to both sides
+        let side = if slot % 2 == 1 { -1.0 } else { 1.0 };
+        let pair = slot.div_ceil(2) as f32;
+
+        match self {
+            FormationShape::Echelon => Vec3::new(n, 0.0, n) * spacing,
+            FormationShape::Fingertip => match slot {
+                0 => Vec3::ZERO,
+                // the element lead sits right, and its wingman further right again
+                2 => Vec3::new(1.0, 0.0, 1.0) * spacing,
+                3 => Vec3::new(2.0, 0.0, 2.0) * spacing,
+                _ => Vec3::new(side * pair, 0.0, pair) * spacing,
+            },
+            FormationShape::LineAbreast => Vec3::new(side * pair, 0.0, 0.0) * spacing,
+            FormationShape::Trail => Vec3::new(0.0, -0.1 * n, n) * spacing,
+        }
+    }
+}
+
+/// Marks the lead of a flight and the shape it wants its wingmen in
+#[derive(Component)]
+pub struct Formation {
+    pub shape: FormationShape,
+    pub spacing: f32,
+}
+
+impl Default for Formation {
+    fn default() -> Self {
+        Formation {
+            shape: FormationShape::Fingertip,
+            spacing: DEFAULT_SPACING,
+        }
+    }
+}
+
+/// A member of a flight, holding a slot on its lead
+#[derive(Component)]
+pub struct Wingman {
+    pub lead: Entity,
+    pub slot: usize,
+    speed_pid: Pid,
+}
+
+impl Wingman {
+    pub fn new(lead: Entity, slot: usize) -> Self {
+        Wingman {
+            lead,
+            slot,
+            speed_pid: Pid::new(0.02, 0.002, 0.0, MAX_SPEED_ADJUSTMENT),
+        }
+    }
+
+    /// Where to slot in, in world space. The slots turn with the lead's heading but stay level
+    /// as it banks and climbs.
+    pub fn slot_position(&self, lead: &SpatialEntry, formation: &Formation) -> Vec3 {
+        let heading = (-lead.forward.x).atan2(-lead.forward.z);
+        let rotation = Quat::from_rotation_y(heading);
+        lead.position + rotation * formation.shape.slot_offset(self.slot, formation.spacing)
+    }
+
+    /// Steering point and speed that keep station on the lead
+    pub fn station_keeping(
+        &mut self,
+        lead: &SpatialEntry,
+        formation: &Formation,
+        position: Vec3,
+        dt: f32,
+    ) -> (Vec3, f32) {
+        let slot = self.slot_position(lead, formation);
+        let lead_speed = lead.velocity.length();
+
+        // positive when we're behind the slot and need to speed up
+        let along_track_error = (slot - position).dot(lead.forward);
+        let adjustment = utils::clamp(
+            self.speed_pid.update(along_track_error, dt),
+            -MAX_SPEED_ADJUSTMENT,
+            MAX_SPEED_ADJUSTMENT,
+        );
+
+        (
+            slot + lead.forward * STATION_LOOKAHEAD,
+            lead_speed * (1.0 + adjustment),
+        )
+    }
+}
+
+pub struct FormationPlugin;
+
+impl Plugin for FormationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(formation_keys_system).add_system_set(
+            SystemSet::new()
+                .with_run_criteria(SimTick)
+                .with_system(
+                    formation_change_system
+                        .after(RecorderUpdate)
+                        .before(SpatialIndexUpdate),
+                )
+                .with_system(
+                    lost_lead_system
+                        .after(SpatialIndexUpdate)
+                        .after(DamageUpdate)
+                        .after(ai::destroyed_system),
+                ),
+        );
+    }
+}
+
+/// F asks for the next formation on the next tick, where it's recorded along with the other
+/// controls
+fn formation_keys_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    recorder: Res<Recorder>,
+    mut controls: ResMut<Controls>,
+) {
+    // the recording changes formation on playback
+    if let Recorder::Playing { .. } = *recorder {
+        return;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::F) {
+        controls.formation = true;
+    }
+}
+
+fn formation_change_system(
+    mut controls: ResMut<Controls>,
+    mut query: Query<&mut Formation, With<Player>>,
+) {
+    if !std::mem::take(&mut controls.formation) {
+        return;
+    }
+
+    for mut formation in query.iter_mut() {
+        formation.shape = formation.shape.next();
+        info!("Formation: {:?}", formation.shape);
+    }
+}
+
+/// Wingmen whose lead has gone, and who weren't handed on to a new lead, fly on alone; the
+/// player's stay on its wing when it's shot down, as it's straight back in the air
+fn lost_lead_system(
+    mut commands: Commands,
+    mut events: EventReader<AircraftDestroyed>,
+    index: Res<SpatialIndex>,
+    query: Query<(Entity, &Wingman)>,
+    players: Query<(), With<Player>>,
+) {
+    let destroyed: Vec<Entity> = events
+        .iter()
+        .map(|event| event.entity)
+        .filter(|entity| players.get(*entity).is_err())
+        .collect();
+
+    for (entity, wingman) in query.iter() {
+        if destroyed.contains(&wingman.lead) || index.get(wingman.lead).is_none() {
+            commands.entity(entity).remove::<Wingman>();
+        }
+    }
+}
@@ -0,0 +1,289 @@
+use bevy::{input::gamepad, prelude::*};
+
+use super::utils;
+
+pub struct GamepadPlugin;
+
+impl Plugin for GamepadPlugin {
+    fn build(&self, app: &mut App) {
+        // deadzones are applied by our own bindings, so let bevy pass the raw values through
+        app.insert_resource(gamepad::GamepadSettings {
+            default_axis_settings: gamepad::AxisSettings {
+                positive_high: 1.0,
+                positive_low: 0.0,
+                negative_high: -1.0,
+                negative_low: 0.0,
+                threshold: 0.0,
+            },
+            ..default()
+        })
+        .insert_resource(GamepadBindings::default());
+    }
+}
+
+/// How an axis responds once it is outside the deadzone
+#[derive(Clone, Copy)]
+pub enum ResponseCurve {
+    Linear,
+    /// blends between linear (0.0) and cubic (1.0) for finer control around the centre
+    Exponential(f32),
+    /// blends between linear (0.0) and smoothstep (1.0), soft at both the centre and the ends
+    SCurve(f32),
+}
+
+#[derive(Clone, Copy)]
+pub struct AxisBinding {
+    pub axis: GamepadAxisType,
+    pub deadzone: f32,
+    pub curve: ResponseCurve,
+    pub inverted: bool,
+    pub sensitivity: f32,
+}
+
+impl AxisBinding {
+    pub fn new(axis: GamepadAxisType) -> Self {
+        AxisBinding {
+            axis,
+            deadzone: 0.1,
+            curve: ResponseCurve::Linear,
+            inverted: false,
+            sensitivity: 1.0,
+        }
+    }
+
+    pub fn with_curve(mut self, curve: ResponseCurve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    pub fn inverted(mut self) -> Self {
+        self.inverted = !self.inverted;
+        self
+    }
+
+    /// maps a raw centred axis value (-1.0 to 1.0) to a control deflection
+    pub fn apply(&self, raw: f32) -> f32 {
+        let magnitude = raw.abs();
+        if magnitude <= self.deadzone {
+            return 0.0;
+        }
+
+        let x = utils::clamp(
+            (magnitude - self.deadzone) / (1.0 - self.deadzone),
+            0.0,
+            1.0,
+        );
+        let value = self.shape(x) * raw.signum() * self.sensitivity;
+
+        if self.inverted {
+            utils::clamp(-value, -1.0, 1.0)
+        } else {
+            utils::clamp(value, -1.0, 1.0)
+        }
+    }
+
+    /// maps a raw absolute axis value (-1.0 to 1.0) to a lever position (0.0 to 1.0),
+    /// with the deadzone at the idle end
+    pub fn apply_absolute(&self, raw: f32) -> f32 {
+        let raw = if self.inverted { -raw } else { raw };
+        let position = (raw + 1.0) / 2.0;
+        if position <= self.deadzone {
+            return 0.0;
+        }
+
+        let x = utils::clamp((position - self.deadzone) / (1.0 - self.deadzone), 0.0, 1.0);
+        utils::clamp(self.shape(x) * self.sensitivity, 0.0, 1.0)
+    }
+
+    fn shape(&self, x: f32) -> f32 {
+        match self.curve {
+            ResponseCurve::Linear => x,
+            ResponseCurve::Exponential(amount) => utils::lerp(x, x.powi(3), amount),
+            ResponseCurve::SCurve(amount) => utils::lerp(x, x * x * (3.0 - 2.0 * x), amount),
+        }
+    }
+}
+
+pub struct GamepadBindings {
+    pub pitch: AxisBinding,
+    pub roll: AxisBinding,
+    pub yaw: AxisBinding,
+    /// absolute throttle lever, as found on a HOTAS
+    pub throttle: AxisBinding,
+    pub throttle_up: GamepadButtonType,
+    pub throttle_down: GamepadButtonType,
+    pub fire: GamepadButtonType,
+    pub launch: GamepadButtonType,
+    pub launch_radar: GamepadButtonType,
+    pub catapult: GamepadButtonType,
+    /// toggled, rather than held
+    pub gear: GamepadButtonType,
+    pub hook: GamepadButtonType,
+}
+
+impl Default for GamepadBindings {
+    fn default() -> Self {
+        // sticks read positive when pushed forwards or right,
+        // which is nose down and roll/yaw right in our control convention
+        GamepadBindings {
+            pitch: AxisBinding::new(GamepadAxisType::LeftStickY)
+                .with_curve(ResponseCurve::Exponential(0.5))
+                .inverted(),
+            roll: AxisBinding::new(GamepadAxisType::LeftStickX)
+                .with_curve(ResponseCurve::Exponential(0.5))
+                .inverted(),
+            yaw: AxisBinding::new(GamepadAxisType::RightStickX)
+                .with_curve(ResponseCurve::SCurve(0.5))
+                .inverted(),
+            throttle: AxisBinding {
+                deadzone: 0.02,
+                ..AxisBinding::new(GamepadAxisType::RightZ)
+            },
+            throttle_up: GamepadButtonType::RightTrigger2,
+            throttle_down: GamepadButtonType::LeftTrigger2,
+            fire: GamepadButtonType::RightTrigger,
+            launch: GamepadButtonType::LeftTrigger,
+            launch_radar: GamepadButtonType::North,
+            catapult: GamepadButtonType::West,
+            gear: GamepadButtonType::DPadDown,
+            hook: GamepadButtonType::DPadUp,
+        }
+    }
+}
+
+/// Analog state of the first connected gamepad
+pub struct GamepadInput {
+    pub pitch: f32,
+    pub roll: f32,
+    pub yaw: f32,
+    /// raw throttle axis, if the device has one
+    pub throttle: Option<f32>,
+    pub throttle_up: bool,
+    pub throttle_down: bool,
+    pub fire: bool,
+    pub launch: bool,
+    pub launch_radar: bool,
+    pub catapult: bool,
+}
+
+pub fn read(
+    gamepads: &Gamepads,
+    axes: &Axis<GamepadAxis>,
+    buttons: &Input<GamepadButton>,
+    bindings: &GamepadBindings,
+) -> Option<GamepadInput> {
+    let gamepad = *gamepads.iter().next()?;
+
+    let axis = |binding: &AxisBinding| {
+        binding.apply(axes.get(GamepadAxis(gamepad, binding.axis)).unwrap_or(0.0))
+    };
+
+    Some(GamepadInput {
+        pitch: axis(&bindings.pitch),
+        roll: axis(&bindings.roll),
+        yaw: axis(&bindings.yaw),
+        throttle: axes.get(GamepadAxis(gamepad, bindings.throttle.axis)),
+        throttle_up: buttons.pressed(GamepadButton(gamepad, bindings.throttle_up)),
+        throttle_down: buttons.pressed(GamepadButton(gamepad, bindings.throttle_down)),
+        fire: buttons.pressed(GamepadButton(gamepad, bindings.fire)),
+        launch: buttons.pressed(GamepadButton(gamepad, bindings.launch)),
+        launch_radar: buttons.pressed(GamepadButton(gamepad, bindings.launch_radar)),
+        catapult: buttons.pressed(GamepadButton(gamepad, bindings.catapult)),
+    })
+}
+
+/// whether a button on the first connected gamepad went down this frame
+pub fn just_pressed(
+    gamepads: &Gamepads,
+    buttons: &Input<GamepadButton>,
+    button: GamepadButtonType,
+) -> bool {
+    gamepads
+        .iter()
+        .next()
+        .is_some_and(|gamepad| buttons.just_pressed(GamepadButton(*gamepad, button)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(curve: ResponseCurve) -> AxisBinding {
+        AxisBinding::new(GamepadAxisType::LeftStickX).with_curve(curve)
+    }
+
+    const CURVES: [ResponseCurve; 3] = [
+        ResponseCurve::Linear,
+        ResponseCurve::Exponential(0.5),
+        ResponseCurve::SCurve(1.0),
+    ];
+
+    #[test]
+    fn deadzone_edge_starts_from_zero() {
+        for curve in CURVES {
+            let binding = binding(curve);
+            assert_eq!(binding.apply(0.0), 0.0);
+            assert_eq!(binding.apply(binding.deadzone), 0.0);
+            assert_eq!(binding.apply(-binding.deadzone), 0.0);
+
+            // no jump on leaving the deadzone
+            let just_out = binding.apply(binding.deadzone + 0.001);
+            assert!(just_out > 0.0 && just_out < 0.01, "{}", just_out);
+            let just_out = binding.apply(-binding.deadzone - 0.001);
+            assert!(just_out < 0.0 && just_out > -0.01, "{}", just_out);
+        }
+    }
+
+    #[test]
+    fn curves_keep_the_sign_and_saturate() {
+        for curve in CURVES {
+            let binding = binding(curve);
+            assert_eq!(binding.apply(1.0), 1.0);
+            assert_eq!(binding.apply(-1.0), -1.0);
+            assert!(binding.apply(0.5) > 0.0 && binding.apply(0.5) < 1.0);
+            assert_eq!(binding.apply(-0.5), -binding.apply(0.5));
+
+            let inverted = binding.inverted();
+            assert_eq!(inverted.apply(0.5), -binding.apply(0.5));
+            assert_eq!(inverted.apply(-1.0), 1.0);
+
+            // extra sensitivity reaches full deflection sooner, but never beyond it
+            let sensitive = AxisBinding {
+                sensitivity: 2.0,
+                ..binding
+            };
+            assert_eq!(sensitive.apply(0.9), 1.0);
+            assert_eq!(sensitive.apply(-0.9), -1.0);
+        }
+
+        // softer around the centre than linear
+        let linear = binding(ResponseCurve::Linear).apply(0.4);
+        assert!(binding(ResponseCurve::Exponential(0.5)).apply(0.4) < linear);
+        assert!(binding(ResponseCurve::SCurve(1.0)).apply(0.4) < linear);
+    }
+
+    #[test]
+    fn sticks_forwards_and_right_fly_nose_down_and_to_the_right() {
+        // the same as W, D and E on the keyboard
+        let bindings = GamepadBindings::default();
+        assert_eq!(bindings.pitch.apply(1.0), -1.0);
+        assert_eq!(bindings.roll.apply(1.0), -1.0);
+        assert_eq!(bindings.yaw.apply(1.0), -1.0);
+    }
+
+    #[test]
+    fn throttle_lever_idles_in_its_deadzone() {
+        let binding = AxisBinding {
+            deadzone: 0.02,
+            ..binding(ResponseCurve::Linear)
+        };
+        assert_eq!(binding.apply_absolute(-1.0), 0.0);
+        assert_eq!(binding.apply_absolute(-0.97), 0.0);
+        assert_eq!(binding.apply_absolute(1.0), 1.0);
+        assert!((binding.apply_absolute(0.0) - 0.48 / 0.98).abs() < 1e-6);
+
+        let inverted = binding.inverted();
+        assert_eq!(inverted.apply_absolute(1.0), 0.0);
+        assert_eq!(inverted.apply_absolute(-1.0), 1.0);
+    }
+}
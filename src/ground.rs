@@ -0,0 +1,587 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use rand::Rng;
+use serde::Deserialize;
+
+use super::ai::sensors::{Radar, NOTCH_SPEED};
+use super::damage::{
+    AircraftDestroyed, DamageUpdate, Hit, SurfaceDestroyed, WeaponSystem, Zone, ZONE_COUNT,
+};
+use super::faction::{Faction, Factions};
+use super::mission::{Mission, MissionRestart, RestartMission};
+use super::sim_time::{SimClock, SimId, SimIds, SimRng, SimRngReseed, SimTick};
+use super::spatial::{Domain, SpatialEntry, SpatialIndex, SpatialIndexUpdate};
+use super::terrain::HeightMap;
+
+/// radars sit this far above the unit's base
+const MAST_HEIGHT: f32 = 20.0;
+/// aircraft flying side on within this height of a site's radar hide in the ground clutter
+const CLUTTER_HEIGHT: f32 = 500.0;
+/// ticks between a SAM site's searches while it has no target, staggered between sites
+const SEARCH_SWEEP_TICKS: u64 = 30;
+const SAM_SEARCH_RANGE: f32 = 15000.0;
+/// the track radar can only guide missiles at targets this close
+const SAM_TRACK_RANGE: f32 = 9000.0;
+/// ticks the track radar has to hold a target before a missile goes
+const SAM_LOCK_TICKS: u32 = 90;
+const SAM_RELOAD_TICKS: u32 = 300;
+const SAM_MISSILES: u32 = 4;
+/// in units per tick
+const MISSILE_SPEED: f32 = 120.0;
+/// radians per tick
+const MISSILE_TURN_RATE: f32 = 0.08;
+const MISSILE_FLIGHT_TICKS: u32 = 240;
+/// the warhead goes off when the target passes this close
+const MISSILE_FUSE_RANGE: f32 = 30.0;
+const MISSILE_DAMAGE: f32 = 70.0;
+const AAA_RANGE: f32 = 2500.0;
+const AAA_BURST_TICKS: u32 = 20;
+/// chance a burst hits a slow target at point blank range, falling off to nothing at full range
+const AAA_HIT_CHANCE: f32 = 0.4;
+/// targets faster than this, in units per tick, are harder for the gunners to lead
+const AAA_TRACKING_SPEED: f32 = 30.0;
+const AAA_DAMAGE: f32 = 10.0;
+/// in units per tick
+const VEHICLE_SPEED: f32 = 2.5;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Deserialize)]
+pub enum GroundKind {
+    /// search and track radars with a battery of missile launchers
+    Sam,
+    /// radar laid anti-aircraft guns
+    Aaa,
+    /// trucks and armour, which drive their route if the mission gives them one
+    Vehicle,
+    /// buildings, bunkers and depots
+    Static,
+}
+
+impl GroundKind {
+    fn health(self) -> f32 {
+        match self {
+            GroundKind::Sam => 150.0,
+            GroundKind::Aaa => 80.0,
+            GroundKind::Vehicle => 50.0,
+            GroundKind::Static => 300.0,
+        }
+    }
+
+    /// width, height and length of the stand in model
+    fn size(self) -> Vec3 {
+        match self {
+            GroundKind::Sam => Vec3::new(60.0, 30.0, 120.0),
+            GroundKind::Aaa => Vec3::new(40.0, 25.0, 60.0),
+            GroundKind::Vehicle => Vec3::new(25.0, 20.0, 60.0),
+            GroundKind::Static => Vec3::new(200.0, 120.0, 200.0),
+        }
+    }
+
+    fn colour(self) -> Color {
+        match self {
+            GroundKind::Sam => Color::rgb(0.35, 0.4, 0.25),
+            GroundKind::Aaa => Color::rgb(0.3, 0.32, 0.22),
+            GroundKind::Vehicle => Color::rgb(0.55, 0.5, 0.35),
+            GroundKind::Static => Color::rgb(0.45, 0.45, 0.45),
+        }
+    }
+
+    fn radar(self) -> Option<Radar> {
+        match self {
+            GroundKind::Sam => Some(SamSite::RADAR),
+            GroundKind::Aaa => Some(Radar {
+                range: AAA_RANGE * 2.0,
+                cone: PI,
+            }),
+            GroundKind::Vehicle | GroundKind::Static => None,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct GroundUnit {
+    kind: GroundKind,
+    pub health: f32,
+}
+
+/// Search and track radars and missile launchers, on land or on a ship
+#[derive(Component, Default)]
+pub struct SamSite {
+    target: Option<Entity>,
+    /// ticks the track radar has held the target for, 0 while it's out of reach
+    tracked_ticks: u32,
+    missiles: u32,
+    reload_ticks: u32,
+}
+
+impl SamSite {
+    pub const RADAR: Radar = Radar {
+        range: SAM_SEARCH_RANGE,
+        cone: PI,
+    };
+
+    /// with a full load of missiles
+    pub fn loaded() -> Self {
+        SamSite {
+            missiles: SAM_MISSILES,
+            ..default()
+        }
+    }
+}
+
+#[derive(Component, Default)]
+pub struct Aaa {
+    cooldown_ticks: u32,
+}
+
+/// Points a vehicle drives round, in order
+#[derive(Component)]
+pub struct Route {
+    points: Vec<Vec3>,
+    next: usize,
+}
+
+/// A surface to air missile, guided by the site that launched it for as long as it holds the lock
+#[derive(Component)]
+pub struct SamMissile {
+    site: Entity,
+    target: Entity,
+    velocity: Vec3,
+    ticks_left: u32,
+}
+
+struct MissileModel {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+pub struct GroundPlugin;
+
+impl Plugin for GroundPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(setup)
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                restart_system
+                    .label(MissionRestart::Ground)
+                    .after(MissionRestart::Aircraft),
+            )
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(SimTick)
+                    .with_system(route_system.after(SpatialIndexUpdate))
+                    .with_system(ground_height_system.after(route_system))
+                    // after the aircraft's weapons, so everything fired is numbered in order
+                    .with_system(
+                        sam_system
+                            .after(SpatialIndexUpdate)
+                            .after(WeaponSystem::Missiles),
+                    )
+                    .with_system(
+                        aaa_system
+                            .label(WeaponSystem::Ground)
+                            .after(SpatialIndexUpdate)
+                            .after(WeaponSystem::Missiles)
+                            .after(SimRngReseed)
+                            .before(DamageUpdate),
+                    )
+                    .with_system(
+                        sam_missile_system
+                            .label(WeaponSystem::Ground)
+                            .after(sam_system)
+                            .after(aaa_system)
+                            .before(DamageUpdate),
+                    )
+                    .with_system(hit_system.label(DamageUpdate))
+                    .with_system(destroyed_system.after(DamageUpdate)),
+            );
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(MissileModel {
+        mesh: meshes.add(Mesh::from(shape::Box::new(4.0, 4.0, 30.0))),
+        material: materials.add(Color::WHITE.into()),
+    });
+}
+
+/// Clears away the ground units and their missiles, and places the mission's ground units
+/// again, with a box standing in for each
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn restart_system(
+    mut commands: Commands,
+    mut events: EventReader<RestartMission>,
+    mut ids: ResMut<SimIds>,
+    mission: Res<Mission>,
+    factions: Res<Factions>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    query: Query<Entity, Or<(With<GroundUnit>, With<SamMissile>)>>,
+) {
+    if events.iter().count() == 0 {
+        return;
+    }
+
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let mut models = HashMap::new();
+    for placement in &mission.ground {
+        let faction = match factions.find(&placement.faction) {
+            Some(faction) => faction,
+            None => {
+                warn!(
+                    "{:?} placed for unknown faction {}",
+                    placement.kind, placement.faction
+                );
+                continue;
+            }
+        };
+
+        let kind = placement.kind;
+        let (mesh, material) = models
+            .entry(kind)
+            .or_insert_with(|| {
+                let size = kind.size();
+                // sat on its base rather than its middle, so it can be put straight on the ground
+                let mesh = Mesh::from(shape::Box {
+                    min_x: -size.x / 2.0,
+                    max_x: size.x / 2.0,
+                    min_y: 0.0,
+                    max_y: size.y,
+                    min_z: -size.z / 2.0,
+                    max_z: size.z / 2.0,
+                });
+                (meshes.add(mesh), materials.add(kind.colour().into()))
+            })
+            .clone();
+
+        let (x, z) = placement.position;
+        let translation = Vec3::new(x, 0.0, z);
+        let mut unit = commands.spawn_bundle(PbrBundle {
+            mesh,
+            material,
+            transform: Transform::from_translation(translation)
+                .with_rotation(Quat::from_rotation_y(-placement.heading.to_radians())),
+            ..default()
+        });
+        unit.insert(ids.allocate())
+            .insert(faction)
+            .insert(GroundUnit {
+                kind,
+                health: kind.health(),
+            });
+        if let Some(radar) = kind.radar() {
+            unit.insert(radar);
+        }
+
+        match kind {
+            GroundKind::Sam => {
+                unit.insert(SamSite::loaded());
+            }
+            GroundKind::Aaa => {
+                unit.insert(Aaa::default());
+            }
+            GroundKind::Vehicle if !placement.route.is_empty() => {
+                let points = std::iter::once(placement.position)
+                    .chain(placement.route.iter().copied())
+                    .map(|(x, z)| Vec3::new(x, 0.0, z))
+                    .collect();
+                unit.insert(Route { points, next: 1 });
+            }
+            GroundKind::Vehicle | GroundKind::Static => {}
+        }
+    }
+}
+
+fn route_system(mut query: Query<(&mut Transform, &mut Route)>) {
+    for (mut transform, mut route) in query.iter_mut() {
+        let point = route.points[route.next];
+        let offset = Vec3::new(
+            point.x - transform.translation.x,
+            0.0,
+            point.z - transform.translation.z,
+        );
+        if offset.length() < VEHICLE_SPEED {
+            route.next = (route.next + 1) % route.points.len();
+            continue;
+        }
+
+        let direction = offset.normalize();
+        transform.translation += direction * VEHICLE_SPEED;
+        transform.rotation = Quat::from_rotation_arc(-Vec3::Z, direction);
+    }
+}
+
+/// Keeps ground units sat on the terrain, which isn't known until the city has loaded
+fn ground_height_system(
+    height_map: Res<HeightMap>,
+    mut query: Query<&mut Transform, With<GroundUnit>>,
+) {
+    for mut transform in query.iter_mut() {
+        transform.translation.y = height_map.height(transform.translation);
+    }
+}
+
+/// Whether a site's radar at `position` can see `entry`: a hostile aircraft within `range`,
+/// clear of the ground clutter and not hidden behind the terrain or buildings
+fn visible(
+    position: Vec3,
+    range: f32,
+    faction: Faction,
+    entry: &SpatialEntry,
+    factions: &Factions,
+    height_map: &HeightMap,
+) -> bool {
+    if entry.domain != Domain::Air
+        || !factions.hostile(faction, entry.faction)
+        || entry.position.distance(position) >= range
+    {
+        return false;
+    }
+
+    let line_of_sight = (entry.position - position).normalize_or_zero();
+    let notched = entry.velocity.dot(line_of_sight).abs() < NOTCH_SPEED
+        && entry.position.y - position.y < CLUTTER_HEIGHT;
+    !notched && height_map.line_of_sight(position, entry.position)
+}
+
+/// Searches for the nearest hostile, locks it with the track radar once it comes in reach,
+/// and launches at it once the lock is solid
+#[allow(clippy::too_many_arguments)]
+fn sam_system(
+    mut commands: Commands,
+    clock: Res<SimClock>,
+    index: Res<SpatialIndex>,
+    factions: Res<Factions>,
+    height_map: Res<HeightMap>,
+    model: Res<MissileModel>,
+    mut ids: ResMut<SimIds>,
+    mut query: Query<(Entity, &SimId, &Transform, &Faction, &Radar, &mut SamSite)>,
+) {
+    // sites on land and on ships are stored apart, so they're sorted to launch in order
+    let mut sites: Vec<_> = query.iter_mut().collect();
+    sites.sort_unstable_by_key(|(_, id, ..)| **id);
+
+    for (entity, id, transform, faction, radar, mut site) in sites {
+        site.reload_ticks = site.reload_ticks.saturating_sub(1);
+        let position = transform.translation + Vec3::Y * MAST_HEIGHT;
+        let sees = |entry: &SpatialEntry, range| {
+            visible(position, range, *faction, entry, &factions, &height_map)
+        };
+
+        // hold on to the target while the search radar can still see it
+        site.target = site.target.filter(|target| {
+            index
+                .get(*target)
+                .is_some_and(|entry| sees(entry, radar.range))
+        });
+        if site.target.is_none() && (clock.tick + id.0 as u64).is_multiple_of(SEARCH_SWEEP_TICKS) {
+            site.target = index
+                .nearest(position, radar.range, |entry| sees(entry, radar.range))
+                .map(|entry| entry.entity);
+            site.tracked_ticks = 0;
+        }
+
+        let target = match site.target.and_then(|target| index.get(target)) {
+            Some(target) => target,
+            None => continue,
+        };
+        if !sees(target, SAM_TRACK_RANGE) {
+            site.tracked_ticks = 0;
+            continue;
+        }
+        site.tracked_ticks += 1;
+
+        if site.tracked_ticks < SAM_LOCK_TICKS || site.reload_ticks > 0 || site.missiles == 0 {
+            continue;
+        }
+        site.missiles -= 1;
+        site.reload_ticks = SAM_RELOAD_TICKS;
+
+        // straight up off the rail, the guidance turns it onto the target
+        let velocity = Vec3::Y * MISSILE_SPEED;
+        commands
+            .spawn_bundle(PbrBundle {
+                mesh: model.mesh.clone(),
+                material: model.material.clone(),
+                transform: Transform::from_translation(position)
+                    .looking_at(position + velocity, Vec3::Z),
+                ..default()
+            })
+            .insert(SamMissile {
+                site: entity,
+                target: target.entity,
+                velocity,
+                ticks_left: MISSILE_FLIGHT_TICKS,
+            })
+            .insert(ids.allocate());
+    }
+}
+
+/// Flies each missile towards where its target is heading while the site holds the lock,
+/// and sets it off when the target passes close enough
+fn sam_missile_system(
+    mut commands: Commands,
+    index: Res<SpatialIndex>,
+    height_map: Res<HeightMap>,
+    sites: Query<&SamSite>,
+    mut missiles: Query<(Entity, &SimId, &mut Transform, &mut SamMissile)>,
+    mut hits: EventWriter<Hit>,
+) {
+    // in launch order, so their hits come in the same order every run
+    let mut missiles: Vec<_> = missiles.iter_mut().collect();
+    missiles.sort_unstable_by_key(|(_, id, ..)| **id);
+
+    for (entity, _, mut transform, mut missile) in missiles {
+        let position = transform.translation;
+        let target = index.get(missile.target);
+
+        // command guided, it only turns while the site still has the target locked
+        let guided = sites
+            .get(missile.site)
+            .is_ok_and(|site| site.target == Some(missile.target) && site.tracked_ticks > 0);
+        if let Some(target) = target.filter(|_| guided) {
+            let time_to_go = target.position.distance(position) / MISSILE_SPEED;
+            let aim = target.position + target.velocity * time_to_go;
+            let current = missile.velocity.normalize();
+            let wanted = (aim - position).normalize_or_zero();
+            let angle = current.angle_between(wanted);
+            if angle > 0.0 {
+                let turn = (MISSILE_TURN_RATE / angle).min(1.0);
+                missile.velocity = current.lerp(wanted, turn).normalize() * MISSILE_SPEED;
+            }
+        }
+
+        // closest approach over the whole tick, as it covers more than its fuse range in one
+        if let Some(target) = target {
+            let offset = target.position - position;
+            let closing = target.velocity - missile.velocity;
+            let time = if closing.length_squared() > 0.0 {
+                (-offset.dot(closing) / closing.length_squared()).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            if (offset + closing * time).length() < MISSILE_FUSE_RANGE {
+                hits.send(Hit {
+                    entity: target.entity,
+                    zone: None,
+                    damage: MISSILE_DAMAGE,
+                });
+                commands.entity(entity).despawn();
+                continue;
+            }
+        }
+
+        transform.translation += missile.velocity;
+        let ahead = transform.translation + missile.velocity;
+        transform.look_at(ahead, Vec3::Y);
+
+        missile.ticks_left = missile.ticks_left.saturating_sub(1);
+        if missile.ticks_left == 0
+            || transform.translation.y < height_map.height(transform.translation)
+        {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Fires a burst at the nearest hostile in reach every so often, which may or may not hit
+fn aaa_system(
+    index: Res<SpatialIndex>,
+    factions: Res<Factions>,
+    height_map: Res<HeightMap>,
+    mut rng: ResMut<SimRng>,
+    mut query: Query<(&SimId, &Transform, &Faction, &mut Aaa)>,
+    mut hits: EventWriter<Hit>,
+) {
+    // in `SimId` order, so the guns draw from the RNG in the same order every run
+    let mut guns: Vec<_> = query.iter_mut().collect();
+    guns.sort_unstable_by_key(|(id, ..)| **id);
+
+    for (_, transform, faction, mut aaa) in guns {
+        aaa.cooldown_ticks = aaa.cooldown_ticks.saturating_sub(1);
+        if aaa.cooldown_ticks > 0 {
+            continue;
+        }
+
+        let position = transform.translation + Vec3::Y * MAST_HEIGHT;
+        let target = index
+            .within_radius(position, AAA_RANGE)
+            .filter(|entry| visible(position, AAA_RANGE, *faction, entry, &factions, &height_map))
+            .map(|entry| (entry, entry.position.distance(position)))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+        let (target, distance) = match target {
+            Some(target) => target,
+            None => continue,
+        };
+        aaa.cooldown_ticks = AAA_BURST_TICKS;
+
+        // the rounds spread out with range, and fast movers are hard to lead
+        let speed = target.velocity.length().max(AAA_TRACKING_SPEED);
+        let chance = AAA_HIT_CHANCE * (1.0 - distance / AAA_RANGE) * AAA_TRACKING_SPEED / speed;
+        if rng.rng.gen::<f32>() < chance {
+            // shells burst all over the airframe, there's no telling which part they catch
+            hits.send(Hit {
+                entity: target.entity,
+                zone: Some(Zone::ALL[rng.rng.gen_range(0..ZONE_COUNT)]),
+                damage: AAA_DAMAGE,
+            });
+        }
+    }
+}
+
+/// Takes the tick's hits off each unit's health, and removes any with none left
+fn hit_system(
+    mut commands: Commands,
+    mut hits: EventReader<Hit>,
+    mut destroyed: EventWriter<SurfaceDestroyed>,
+    factions: Res<Factions>,
+    mut query: Query<(&Faction, &mut GroundUnit)>,
+) {
+    for hit in hits.iter() {
+        let (faction, mut unit) = match query.get_mut(hit.entity) {
+            Ok(unit) => unit,
+            Err(_) => continue,
+        };
+        // already gone, from an earlier hit this tick
+        if unit.health <= 0.0 {
+            continue;
+        }
+
+        unit.health -= hit.damage;
+        if unit.health <= 0.0 {
+            info!("{} {:?} destroyed", factions.name(*faction), unit.kind);
+            destroyed.send(SurfaceDestroyed { entity: hit.entity });
+            commands.entity(hit.entity).despawn_recursive();
+        }
+    }
+}
+
+/// Sites drop their lock on anything shot down, and missiles still chasing it self-destruct
+fn destroyed_system(
+    mut commands: Commands,
+    mut events: EventReader<AircraftDestroyed>,
+    mut sites: Query<&mut SamSite>,
+    missiles: Query<(Entity, &SamMissile)>,
+) {
+    for event in events.iter() {
+        for mut site in sites.iter_mut() {
+            if site.target == Some(event.entity) {
+                site.target = None;
+                site.tracked_ticks = 0;
+            }
+        }
+        for (entity, missile) in missiles.iter() {
+            if missile.target == event.entity {
+                commands.entity(entity).despawn();
+            }
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use rand::Rng;
+
+use super::ai;
+use super::damage::{DamageUpdate, Hit, WeaponSystem, Zone};
+use super::mission::RestartMission;
+use super::plane::{PlaneSystem, GRAVITY};
+use super::sim_time::{SimId, SimIds, SimRng, SimRngReseed, SimTick};
+use super::spatial::{Domain, SpatialIndex, SpatialIndexUpdate};
+use super::terrain::HeightMap;
+use super::{Controls, Player, AI};
+
+/// speed rounds leave the barrel at, on top of the aircraft's own, in units per tick
+pub const MUZZLE_VELOCITY: f32 = 180.0;
+/// rounds per tick while the trigger is held
+const RATE_OF_FIRE: f32 = 1.0;
+const AMMUNITION: u32 = 480;
+/// half angle of the cone rounds scatter in, in radians
+const DISPERSION: f32 = 0.004;
+/// fraction of a round's speed lost per tick, for each unit per tick it's going
+const DRAG: f32 = 0.00002;
+/// rounds that haven't hit anything by now have fallen away or spent themselves
+const ROUND_TICKS: u32 = 120;
+/// every this many rounds is a tracer
+const TRACER_INTERVAL: u32 = 4;
+const TRACER_LENGTH: f32 = 60.0;
+const ROUND_DAMAGE: f32 = 4.0;
+/// radius of the sphere around an aircraft that a round has to pass through to hit it
+const HIT_RADIUS: f32 = 8.0;
+/// the same around a ground unit or ship, which are far bigger
+const SURFACE_HIT_RADIUS: f32 = 30.0;
+/// rounds leave the muzzle this far ahead of the aircraft's centre, clear of its own hitbox
+const MUZZLE_OFFSET: f32 = 12.0;
+/// fastest an aircraft goes, in units per tick, so hit tests look far enough for one
+const MAX_AIRCRAFT_SPEED: f32 = 150.0;
+
+#[derive(Component)]
+pub struct Gun {
+    pub trigger: bool,
+    pub ammunition: u32,
+    /// part of a round left over from the last tick, at rates of fire that aren't whole per tick
+    pending: f32,
+    fired: u32,
+}
+
+impl Default for Gun {
+    fn default() -> Self {
+        Gun {
+            trigger: false,
+            ammunition: AMMUNITION,
+            pending: 0.0,
+            fired: 0,
+        }
+    }
+}
+
+/// A round in flight
+#[derive(Component)]
+pub struct Round {
+    /// never hit by its own rounds
+    shooter: Entity,
+    velocity: Vec3,
+    ticks_left: u32,
+}
+
+struct TracerModel {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+pub struct GunPlugin;
+
+impl Plugin for GunPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(setup)
+            .add_system_to_stage(CoreStage::PreUpdate, restart_system)
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(SimTick)
+                    // once everything has moved, so rounds leave from where the guns are now
+                    .with_system(
+                        gun_system
+                            .after(PlaneSystem::Flight)
+                            .after(ai::ai_system)
+                            .after(SimRngReseed),
+                    )
+                    .with_system(
+                        round_system
+                            .label(WeaponSystem::Guns)
+                            .after(SpatialIndexUpdate)
+                            .after(gun_system)
+                            .before(DamageUpdate),
+                    ),
+            );
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(TracerModel {
+        mesh: meshes.add(Mesh::from(shape::Box::new(1.0, 1.0, TRACER_LENGTH))),
+        material: materials.add(StandardMaterial {
+            base_color: Color::rgb(1.0, 0.8, 0.3),
+            emissive: Color::rgb(1.0, 0.7, 0.2),
+            unlit: true,
+            ..default()
+        }),
+    });
+}
+
+/// Clears away the rounds in flight
+fn restart_system(
+    mut commands: Commands,
+    mut events: EventReader<RestartMission>,
+    rounds: Query<Entity, With<Round>>,
+) {
+    if events.iter().count() == 0 {
+        return;
+    }
+
+    for entity in rounds.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// Fires every gun with its trigger held, scattering the rounds with the simulation's RNG so a
+/// replay scatters them the same way
+#[allow(clippy::type_complexity)]
+fn gun_system(
+    mut commands: Commands,
+    controls: Res<Controls>,
+    tracer: Res<TracerModel>,
+    mut rng: ResMut<SimRng>,
+    mut ids: ResMut<SimIds>,
+    mut query: Query<(
+        Entity,
+        &SimId,
+        &Transform,
+        &mut Gun,
+        Option<&AI>,
+        Option<&Player>,
+    )>,
+) {
+    // in `SimId` order, so the rounds draw from the RNG in the same order every run
+    let mut guns: Vec<_> = query.iter_mut().collect();
+    guns.sort_unstable_by_key(|(_, id, ..)| **id);
+
+    for (entity, _, transform, mut gun, ai, player) in guns {
+        if player.is_some() {
+            gun.trigger = controls.trigger;
+        }
+        if !gun.trigger || gun.ammunition == 0 {
+            gun.pending = 0.0;
+            continue;
+        }
+
+        let velocity = ai
+            .map(|ai| ai.velocity)
+            .or_else(|| player.map(|player| player.velocity))
+            .unwrap_or(Vec3::ZERO);
+        let forward = transform.forward();
+        let muzzle = transform.translation + forward * MUZZLE_OFFSET;
+
+        gun.pending += RATE_OF_FIRE;
+        while gun.pending >= 1.0 && gun.ammunition > 0 {
+            gun.pending -= 1.0;
+            gun.ammunition -= 1;
+            gun.fired += 1;
+
+            // uniform over the cone's cross section
+            let angle = rng.rng.gen_range(0.0..TAU);
+            let spread = rng.rng.gen::<f32>().sqrt() * DISPERSION;
+            let scatter = transform.local_x() * angle.cos() + transform.local_y() * angle.sin();
+            let direction = (forward + scatter * spread).normalize();
+            let round = Round {
+                shooter: entity,
+                velocity: velocity + direction * MUZZLE_VELOCITY,
+                ticks_left: ROUND_TICKS,
+            };
+            let transform =
+                Transform::from_translation(muzzle).looking_at(muzzle + direction, Vec3::Y);
+
+            if gun.fired % TRACER_INTERVAL == 0 {
+                commands
+                    .spawn_bundle(PbrBundle {
+                        mesh: tracer.mesh.clone(),
+                        material: tracer.material.clone(),
+                        transform,
+                        ..default()
+                    })
+                    .insert(round)
+                    .insert(ids.allocate());
+            } else {
+                commands
+                    .spawn_bundle((transform, GlobalTransform::identity()))
+                    .insert(round)
+                    .insert(ids.allocate());
+            }
+        }
+    }
+}
+
+/// Flies each round under gravity and drag, and hits the first thing it passes through; an
+/// aircraft in whichever part of it the round went through
+fn round_system(
+    mut commands: Commands,
+    index: Res<SpatialIndex>,
+    height_map: Res<HeightMap>,
+    mut rounds: Query<(Entity, &SimId, &mut Transform, &mut Round)>,
+    targets: Query<&Transform, Without<Round>>,
+    mut hits: EventWriter<Hit>,
+) {
+    // tracers and plain rounds are stored apart, so they're sorted to hit in firing order
+    let mut rounds: Vec<_> = rounds.iter_mut().collect();
+    rounds.sort_unstable_by_key(|(_, id, ..)| **id);
+
+    for (entity, _, mut transform, mut round) in rounds {
+        let mut velocity = round.velocity + Vec3::Y * GRAVITY;
+        let speed = velocity.length();
+        velocity -= velocity * speed * DRAG;
+        round.velocity = velocity;
+
+        // tested over the whole tick's flight against where each aircraft is heading, as a
+        // round covers many times an aircraft's size in a tick
+        let position = transform.translation;
+        let hit = index
+            .within_radius(position, speed + MAX_AIRCRAFT_SPEED + SURFACE_HIT_RADIUS)
+            .filter(|entry| entry.entity != round.shooter)
+            .filter_map(|entry| {
+                let radius = match entry.domain {
+                    Domain::Air => HIT_RADIUS,
+                    Domain::Surface => SURFACE_HIT_RADIUS,
+                };
+                // the index was taken before the aircraft moved this tick
+                let offset = entry.position + entry.velocity - position;
+                let closing = entry.velocity - round.velocity;
+                let time = if closing.length_squared() > 0.0 {
+                    (-offset.dot(closing) / closing.length_squared()).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                let miss = offset + closing * time;
+                (miss.length() < radius).then_some((entry, time, miss))
+            })
+            .min_by(|(_, a, _), (_, b, _)| a.total_cmp(b));
+
+        if let Some((target, _, miss)) = hit {
+            if let Ok(struck) = targets.get(target.entity) {
+                // the round passes the aircraft's centre by `miss`, so it's at `-miss` from it
+                let zone = (target.domain == Domain::Air)
+                    .then(|| Zone::at(struck.rotation.inverse() * -miss));
+                hits.send(Hit {
+                    entity: target.entity,
+                    zone,
+                    damage: ROUND_DAMAGE,
+                });
+            }
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        transform.translation += round.velocity;
+        let ahead = transform.translation + round.velocity;
+        transform.look_at(ahead, Vec3::Y);
+
+        round.ticks_left = round.ticks_left.saturating_sub(1);
+        if round.ticks_left == 0
+            || transform.translation.y < height_map.height(transform.translation)
+        {
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -3,7 +3,26 @@ use bevy::{
     prelude::*,
 };
 
-use super::{Camera3d, Controls, Enemy, Player};
+use super::ai::bfm::Bfm;
+use super::ai::orders::{RadioMenu, RADIO_OPTIONS};
+use super::ai::sensors::Contacts;
+use super::ai::state::{AIMode, AIState};
+use super::ai::tactics::{Flight, FlightMember};
+use super::autopilot::{Autopilot, LateralMode, VerticalMode};
+use super::damage::Damage;
+use super::faction::{Faction, Factions};
+use super::gun::Gun;
+use super::landing::{LandingGear, Meatball};
+use super::missile::{LaunchZone, MissileRack, Shot};
+use super::mouse_aim::{ControlScheme, MouseAim};
+use super::recording::Recorder;
+use super::sim_time::SimClock;
+use super::{Camera3d, Controls, Player, AI};
+
+/// the ball is off the lens beyond this many cells from the datum
+const LENS_CELLS: f32 = 2.5;
+/// degrees off the centreline before the landing aid calls for a correction
+const LINEUP_TOLERANCE: f32 = 1.0;
 
 #[derive(Component)]
 struct FpsText;
@@ -14,18 +33,67 @@ struct StallWarningText;
 #[derive(Component)]
 struct SpeedText;
 
+#[derive(Component)]
+struct AutopilotText;
+
+#[derive(Component)]
+struct LaunchZoneText;
+
+#[derive(Component)]
+struct SimRateText;
+
+#[derive(Component)]
+struct RecorderText;
+
+#[derive(Component)]
+struct RadioText;
+
+/// The carrier's landing aid, and the wire caught once down
+#[derive(Component)]
+struct MeatballText;
+
+/// Debug label following an AI aircraft
+#[derive(Component)]
+struct StateLabel(Entity);
+
+/// Whether the AI debug overlay is shown
+#[derive(Default)]
+struct DebugOverlay(bool);
+
+/// Where the mouse is steering towards
+#[derive(Component)]
+struct AimMarker;
+
+/// Where the nose is actually pointing
+#[derive(Component)]
+struct NoseMarker;
+
+/// Box around the player's locked target
 #[derive(Component)]
 struct Reticule(Entity);
 
 pub struct HUDPlugin;
 
 impl Plugin for HUDPlugin {
+    #[allow(clippy::default_constructed_unit_structs)]
     fn build(&self, app: &mut App) {
         app.add_plugin(FrameTimeDiagnosticsPlugin::default())
+            .init_resource::<DebugOverlay>()
             .add_startup_system(setup)
             .add_system(stall_warning_system)
             .add_system(speed_system)
-            // .add_system(reticule_system)
+            .add_system(aim_marker_system)
+            .add_system(autopilot_system)
+            .add_system(launch_zone_text_system)
+            .add_system(sim_rate_system)
+            .add_system(recorder_system)
+            .add_system(meatball_system)
+            .add_system(debug_overlay_toggle_system)
+            .add_system(state_label_spawn_system)
+            .add_system(state_label_system.after(state_label_spawn_system))
+            .add_system(radio_system)
+            .add_system(reticule_spawn_system)
+            .add_system(reticule_system.after(reticule_spawn_system))
             .add_system(fps_system);
     }
 }
@@ -114,6 +182,70 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                                     color: Color::WHITE,
                                 },
                             },
+                            TextSection {
+                                value: "\nGun: ".to_string(),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size: 30.0,
+                                    color: Color::WHITE,
+                                },
+                            },
+                            TextSection {
+                                value: "".to_string(),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size: 30.0,
+                                    color: Color::WHITE,
+                                },
+                            },
+                            TextSection {
+                                value: "\nIR: ".to_string(),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size: 30.0,
+                                    color: Color::WHITE,
+                                },
+                            },
+                            TextSection {
+                                value: "".to_string(),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size: 30.0,
+                                    color: Color::WHITE,
+                                },
+                            },
+                            TextSection {
+                                value: "\nRDR: ".to_string(),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size: 30.0,
+                                    color: Color::WHITE,
+                                },
+                            },
+                            TextSection {
+                                value: "".to_string(),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size: 30.0,
+                                    color: Color::WHITE,
+                                },
+                            },
+                            TextSection {
+                                value: "\nGear: ".to_string(),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size: 30.0,
+                                    color: Color::WHITE,
+                                },
+                            },
+                            TextSection {
+                                value: "".to_string(),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size: 30.0,
+                                    color: Color::WHITE,
+                                },
+                            },
                         ],
                         ..default()
                     },
@@ -162,6 +294,192 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                 })
                 .insert(StallWarningText);
         });
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(5.0),
+                    left: Val::Px(5.0),
+                    ..default()
+                },
+                ..default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 24.0,
+                    color: Color::GREEN,
+                },
+                default(),
+            ),
+            ..default()
+        })
+        .insert(AutopilotText);
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Val::Px(5.0),
+                    left: Val::Px(5.0),
+                    ..default()
+                },
+                ..default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 24.0,
+                    color: Color::GREEN,
+                },
+                default(),
+            ),
+            ..default()
+        })
+        .insert(LaunchZoneText);
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Auto),
+                position_type: PositionType::Absolute,
+                justify_content: JustifyContent::Center,
+                position: Rect {
+                    top: Val::Px(5.0),
+                    ..default()
+                },
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 30.0,
+                            color: Color::YELLOW,
+                        },
+                        default(),
+                    ),
+                    ..default()
+                })
+                .insert(SimRateText);
+        });
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Val::Px(5.0),
+                    right: Val::Px(5.0),
+                    ..default()
+                },
+                ..default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 24.0,
+                    color: Color::RED,
+                },
+                default(),
+            ),
+            ..default()
+        })
+        .insert(RecorderText);
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Percent(40.0),
+                    right: Val::Px(40.0),
+                    ..default()
+                },
+                ..default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 24.0,
+                    color: Color::ORANGE,
+                },
+                TextAlignment {
+                    vertical: VerticalAlign::Top,
+                    horizontal: HorizontalAlign::Center,
+                },
+            ),
+            ..default()
+        })
+        .insert(MeatballText);
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(40.0),
+                    left: Val::Px(5.0),
+                    ..default()
+                },
+                ..default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 20.0,
+                    color: Color::GREEN,
+                },
+                default(),
+            ),
+            ..default()
+        })
+        .insert(RadioText);
+
+    commands
+        .spawn_bundle(marker_bundle("( )", font.clone()))
+        .insert(AimMarker);
+
+    commands
+        .spawn_bundle(marker_bundle("+", font))
+        .insert(NoseMarker);
+}
+
+fn marker_bundle(symbol: &str, font: Handle<Font>) -> TextBundle {
+    TextBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            display: Display::None,
+            ..default()
+        },
+        text: Text::with_section(
+            symbol,
+            TextStyle {
+                font,
+                font_size: 30.0,
+                color: Color::GREEN,
+            },
+            TextAlignment {
+                vertical: VerticalAlign::Center,
+                horizontal: HorizontalAlign::Center,
+            },
+        ),
+        ..default()
+    }
 }
 
 fn fps_system(diagnostics: Res<Diagnostics>, mut query: Query<&mut Text, With<FpsText>>) {
@@ -188,16 +506,88 @@ fn stall_warning_system(
     }
 }
 
-fn speed_system(mut text_query: Query<&mut Text, With<SpeedText>>, controls: Res<Controls>) {
+fn speed_system(
+    mut text_query: Query<&mut Text, With<SpeedText>>,
+    controls: Res<Controls>,
+    weapons_query: Query<(&Gun, &MissileRack), With<Player>>,
+) {
+    let (gun, rack) = weapons_query.single();
     for mut text in text_query.iter_mut() {
         text.sections[1].value = format!("{:.0}", controls.thrust);
+        text.sections[3].value = gun.ammunition.to_string();
+        text.sections[5].value = rack.infrared.to_string();
+        text.sections[7].value = (rack.active + rack.semi_active).to_string();
+        text.sections[9].value = match (controls.gear, controls.hook) {
+            (false, false) => "UP",
+            (false, true) => "UP HOOK",
+            (true, false) => "DOWN",
+            (true, true) => "DOWN HOOK",
+        }
+        .to_string();
+    }
+}
+
+/// Range to the locked target against each missile's launch zone, with how far in the target
+/// couldn't run from a radar missile, and SHOOT once it's inside
+fn launch_zone_text_system(
+    mut text_query: Query<&mut Text, With<LaunchZoneText>>,
+    zone: Res<LaunchZone>,
+) {
+    let line = |name: &str, shot: &Shot| match (shot.zone, shot.no_escape) {
+        (None, _) => format!("{} RNG {:.0}  NO SHOT", name, shot.range),
+        (Some((min, max)), no_escape) => format!(
+            "{} RNG {:.0}  MIN {:.0}  MAX {:.0}{}{}",
+            name,
+            shot.range,
+            min,
+            max,
+            no_escape.map_or(String::new(), |range| format!("  NEZ {:.0}", range)),
+            if shot.in_zone() { "  SHOOT" } else { "" }
+        ),
+    };
+    for mut text in text_query.iter_mut() {
+        let infrared = zone.infrared.map(|shot| line("IR ", &shot));
+        let radar = zone.radar.map(|shot| line("RDR", &shot));
+        text.sections[0].value = infrared
+            .into_iter()
+            .chain(radar)
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+}
+
+/// Keeps a single reticule on whatever the player has locked
+fn reticule_spawn_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    player_query: Query<&Player>,
+    reticule_query: Query<(Entity, &Reticule)>,
+) {
+    let target = player_query.single().target;
+
+    let mut shown = false;
+    for (entity, reticule) in reticule_query.iter() {
+        if Some(reticule.0) == target {
+            shown = true;
+        } else {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    if let (Some(target), false) = (target, shown) {
+        let mut reticule = marker_bundle(
+            "[ ]",
+            asset_server.load("fonts/RobotoMono/RobotoMono-Regular.ttf"),
+        );
+        reticule.text.sections[0].style.color = Color::RED;
+        commands.spawn_bundle(reticule).insert(Reticule(target));
     }
 }
 
 fn reticule_system(
     windows: Res<Windows>,
     mut reticule_query: Query<(&mut Style, &Reticule)>,
-    targets_query: Query<&GlobalTransform, With<Enemy>>,
+    targets_query: Query<&GlobalTransform>,
     camera_query: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
     images: Res<Assets<Image>>,
 ) {
@@ -210,7 +600,7 @@ fn reticule_system(
                     style.display = Display::Flex;
                     style.position = Rect {
                         left: Val::Px(screen_position.x),
-                        top: Val::Px(screen_position.y),
+                        bottom: Val::Px(screen_position.y),
                         ..default()
                     };
                 }
@@ -219,7 +609,277 @@ fn reticule_system(
                 }
             }
         } else {
-            warn!("Could not find reticule for target");
+            style.display = Display::None;
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn aim_marker_system(
+    windows: Res<Windows>,
+    images: Res<Assets<Image>>,
+    scheme: Res<ControlScheme>,
+    aim: Res<MouseAim>,
+    player_query: Query<&Transform, With<Player>>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+    mut aim_query: Query<&mut Style, (With<AimMarker>, Without<NoseMarker>)>,
+    mut nose_query: Query<&mut Style, (With<NoseMarker>, Without<AimMarker>)>,
+) {
+    let (camera, camera_transform) = camera_query.single();
+    let player = player_query.single();
+
+    let place = |style: &mut Style, direction: Vec3| {
+        let point = player.translation + direction * 1000.0;
+        match camera.world_to_screen(&windows, &images, camera_transform, point) {
+            Some(screen_position) if *scheme == ControlScheme::MouseAim => {
+                style.display = Display::Flex;
+                style.position = Rect {
+                    left: Val::Px(screen_position.x),
+                    bottom: Val::Px(screen_position.y),
+                    ..default()
+                };
+            }
+            _ => {
+                style.display = Display::None;
+            }
+        }
+    };
+
+    for mut style in aim_query.iter_mut() {
+        place(&mut style, aim.direction);
+    }
+    for mut style in nose_query.iter_mut() {
+        place(&mut style, player.forward());
+    }
+}
+
+fn autopilot_system(
+    mut text_query: Query<&mut Text, With<AutopilotText>>,
+    autopilot_query: Query<&Autopilot, With<Player>>,
+) {
+    let autopilot = autopilot_query.single();
+
+    let mut modes = Vec::new();
+    match autopilot.lateral {
+        LateralMode::Off => {}
+        LateralMode::WingLeveler => modes.push("LVL".to_string()),
+        LateralMode::Heading(heading) => modes.push(format!(
+            "HDG {:03.0}",
+            heading.to_degrees().rem_euclid(360.0)
+        )),
+        LateralMode::Navigation => modes.push(format!("NAV WP{}", autopilot.active_waypoint + 1)),
+    }
+    match autopilot.vertical {
+        VerticalMode::Off => {}
+        VerticalMode::Altitude(altitude) => modes.push(format!("ALT {:.0}", altitude)),
+        VerticalMode::VerticalSpeed(speed) => modes.push(format!("V/S {:+.1}", speed)),
+    }
+    if let Some(speed) = autopilot.autothrottle {
+        modes.push(format!("A/T {:.0}", speed));
+    }
+
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = if autopilot.engaged() {
+            format!("AP  {}", modes.join("  "))
+        } else {
+            String::new()
+        };
+    }
+}
+
+fn sim_rate_system(mut text_query: Query<&mut Text, With<SimRateText>>, clock: Res<SimClock>) {
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = if clock.paused {
+            "PAUSED".to_string()
+        } else if clock.scale() != 1.0 {
+            format!("x{}", clock.scale())
+        } else {
+            String::new()
+        };
+    }
+}
+
+fn recorder_system(mut text_query: Query<&mut Text, With<RecorderText>>, recorder: Res<Recorder>) {
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = match &*recorder {
+            Recorder::Idle => String::new(),
+            Recorder::Armed => "REC ...".to_string(),
+            Recorder::Recording(recording) => format!("REC {}", recording.frames.len()),
+            Recorder::Loaded(_) => "REPLAY ...".to_string(),
+            Recorder::Playing { recording, tick } => {
+                format!("REPLAY {}/{}", tick, recording.frames.len())
+            }
+        };
+    }
+}
+
+/// The ball against the datum lights, with the calls for being high, low or off the centreline,
+/// and the wire caught once the aircraft is down on the deck
+fn meatball_system(
+    mut text_query: Query<&mut Text, With<MeatballText>>,
+    meatball: Res<Meatball>,
+    gear_query: Query<&LandingGear, With<Player>>,
+) {
+    let gear = gear_query.single();
+    for mut text in text_query.iter_mut() {
+        let section = &mut text.sections[0];
+        section.style.color = Color::ORANGE;
+        section.value = match meatball.ball {
+            Some(ball) => {
+                // the ball drops off the bottom of the lens and the wave off lights come on
+                if ball < -LENS_CELLS {
+                    section.style.color = Color::RED;
+                }
+                let row = ball.round() as i32;
+                let mut lines: Vec<String> = (-2..=2)
+                    .rev()
+                    .map(|cell| {
+                        let light = if cell == row { "O" } else { " " };
+                        if cell == 0 {
+                            format!("== {} ==", light)
+                        } else {
+                            format!("   {}   ", light)
+                        }
+                    })
+                    .collect();
+
+                lines.push(
+                    if ball < -LENS_CELLS {
+                        "WAVE OFF"
+                    } else if ball > 0.5 {
+                        "HIGH"
+                    } else if ball < -0.5 {
+                        "LOW"
+                    } else {
+                        ""
+                    }
+                    .to_string(),
+                );
+                if meatball.lineup > LINEUP_TOLERANCE {
+                    lines.push("<< COME LEFT".to_string());
+                } else if meatball.lineup < -LINEUP_TOLERANCE {
+                    lines.push("COME RIGHT >>".to_string());
+                }
+                lines.join("\n")
+            }
+            None => match gear.last_wire {
+                Some(wire) if gear.on_ground => format!("{} WIRE", wire),
+                _ => String::new(),
+            },
+        };
+    }
+}
+
+fn radio_system(mut text_query: Query<&mut Text, With<RadioText>>, menu: Res<RadioMenu>) {
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = if menu.open {
+            RADIO_OPTIONS
+                .iter()
+                .enumerate()
+                .map(|(i, option)| format!("{}. {}", i + 1, option))
+                .collect::<Vec<_>>()
+                .join("\n")
+        } else {
+            menu.last_call.clone().unwrap_or_default()
+        };
+    }
+}
+
+fn debug_overlay_toggle_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut overlay: ResMut<DebugOverlay>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        overlay.0 = !overlay.0;
+    }
+}
+
+fn state_label_spawn_system(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    query: Query<Entity, Added<AIState>>,
+) {
+    for entity in query.iter() {
+        let mut label = marker_bundle(
+            "",
+            asset_server.load("fonts/RobotoMono/RobotoMono-Regular.ttf"),
+        );
+        label.text.sections[0].style.font_size = 14.0;
+        label.text.sections[0].style.color = Color::YELLOW;
+
+        commands.spawn_bundle(label).insert(StateLabel(entity));
+    }
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn state_label_system(
+    mut commands: Commands,
+    windows: Res<Windows>,
+    images: Res<Assets<Image>>,
+    overlay: Res<DebugOverlay>,
+    mut label_query: Query<(Entity, &StateLabel, &mut Style, &mut Text)>,
+    factions: Res<Factions>,
+    ai_query: Query<(
+        &GlobalTransform,
+        &Faction,
+        &AI,
+        &Damage,
+        &AIState,
+        &Bfm,
+        &Contacts,
+        Option<&FlightMember>,
+    )>,
+    flight_query: Query<&Flight>,
+    camera_query: Query<(&Camera, &GlobalTransform), With<Camera3d>>,
+) {
+    let (camera, camera_transform) = camera_query.single();
+
+    for (label_entity, label, mut style, mut text) in label_query.iter_mut() {
+        let (transform, faction, ai, damage, state, bfm, contacts, member) =
+            match ai_query.get(label.0) {
+                Ok(ai) => ai,
+                Err(_) => {
+                    commands.entity(label_entity).despawn();
+                    continue;
+                }
+            };
+
+        let screen_position = if overlay.0 {
+            camera.world_to_screen(&windows, &images, camera_transform, transform.translation)
+        } else {
+            None
+        };
+
+        match screen_position {
+            Some(screen_position) => {
+                style.display = Display::Flex;
+                style.position = Rect {
+                    left: Val::Px(screen_position.x),
+                    bottom: Val::Px(screen_position.y + 20.0),
+                    ..default()
+                };
+                let mode = match state.mode {
+                    AIMode::Engage | AIMode::Evade => {
+                        format!("{:?} {:?}", state.mode, bfm.manoeuvre)
+                    }
+                    mode => format!("{:?}", mode),
+                };
+                let tactic = member
+                    .and_then(|member| flight_query.get(member.flight).ok())
+                    .map_or(String::new(), |flight| format!(" {:?}", flight.tactic));
+                text.sections[0].value = format!(
+                    "{} {}\nHP {:.0} FUEL {:.0}% TRK {}{}",
+                    factions.name(*faction),
+                    mode,
+                    damage.health(),
+                    ai.fuel * 100.0,
+                    contacts.iter().count(),
+                    tactic
+                );
+            }
+            None => {
+                style.display = Display::None;
+            }
         }
     }
 }
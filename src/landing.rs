@@ -0,0 +1,408 @@
+use bevy::prelude::*;
+
+use super::damage::{DamageUpdate, Hit};
+use super::faction::Faction;
+use super::gamepad::{self, GamepadBindings};
+use super::mission::{MissionOptions, RestartMission};
+use super::naval::Ship;
+use super::plane::{self, PlaneSystem, MAXIMUM_THRUST, MINIMUM_THRUST};
+use super::recording::Recorder;
+use super::sim_time::SimTick;
+use super::terrain::HeightMap;
+use super::{Controls, Player};
+
+/// height of the aircraft's origin above whatever it's standing on
+const GEAR_HEIGHT: f32 = 6.0;
+/// fraction of speed lost per tick with the gear down
+const GEAR_DRAG: f32 = 0.002;
+/// fastest the gear takes the ground at, in units per tick
+const MAX_SINK_RATE: f32 = 4.0;
+/// change in speed per tick rolling on the wheels, and with the brakes on
+const ROLLING_FRICTION: f32 = 0.01;
+const BRAKING: f32 = 0.25;
+
+/// how far below the wheels the hook catches a wire
+const HOOK_REACH: f32 = 3.0;
+const WIRES: usize = 4;
+/// distance from the stern to the first wire, and between wires
+const FIRST_WIRE: f32 = 25.0;
+const WIRE_SPACING: f32 = 15.0;
+/// the aim is to catch this wire, counting from the stern
+const TARGET_WIRE: usize = 3;
+/// how far the wires pay out bringing an aircraft to a stop
+const ARRESTOR_RUNOUT: f32 = 120.0;
+
+/// the shuttle starts this far forward of midships and runs to the bow
+const CATAPULT_START: f32 = 20.0;
+/// change in speed per tick along the stroke
+const CATAPULT_ACCELERATION: f32 = 4.5;
+/// how close to the shuttle, and how slow relative to the deck, an aircraft has to be to hook up
+const CATAPULT_REACH: f32 = 40.0;
+const HOOKUP_SPEED: f32 = 2.0;
+
+/// angle of the glideslope the lens shows, and of each of its five cells
+const GLIDESLOPE_DEGREES: f32 = 3.5;
+const CELL_DEGREES: f32 = 0.75;
+/// the lens can be seen this far astern, and this many degrees either side of the centreline
+const LENS_RANGE: f32 = 8000.0;
+const LENS_AZIMUTH_DEGREES: f32 = 20.0;
+
+/// A carrier's flight deck, in the ship's own frame with the bow along -z
+#[derive(Component)]
+pub struct FlightDeck {
+    /// height of the deck above the ship's origin
+    pub height: f32,
+    pub half_width: f32,
+    pub half_length: f32,
+}
+
+impl FlightDeck {
+    /// where a wire crosses the deck, the first wire being the one nearest the stern
+    fn wire(&self, wire: usize) -> f32 {
+        self.half_length - FIRST_WIRE - WIRE_SPACING * wire as f32
+    }
+
+    fn covers(&self, local: Vec3) -> bool {
+        local.x.abs() <= self.half_width && local.z.abs() <= self.half_length
+    }
+
+    fn catapult_stroke(&self) -> f32 {
+        self.half_length - CATAPULT_START
+    }
+}
+
+struct Catapult {
+    carrier: Entity,
+    /// distance along the stroke, and speed relative to the deck
+    travelled: f32,
+    speed: f32,
+    fired: bool,
+}
+
+struct Arrest {
+    carrier: Entity,
+    /// taken off the speed along the deck each tick until the aircraft stops
+    deceleration: f32,
+}
+
+/// The player's wheels and hook, and what's holding them on the deck
+#[derive(Component, Default)]
+pub struct LandingGear {
+    pub on_ground: bool,
+    catapult: Option<Catapult>,
+    arrested: Option<Arrest>,
+    /// the wire caught on the last trap, counting from the stern
+    pub last_wire: Option<usize>,
+}
+
+/// What the landing aid on the player's nearest carrier shows them
+#[derive(Default)]
+pub struct Meatball {
+    /// cells the ball sits above the datum lights, or None outside the lens's beam
+    pub ball: Option<f32>,
+    /// degrees right of the landing centreline
+    pub lineup: f32,
+}
+
+pub struct LandingPlugin;
+
+impl Plugin for LandingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Meatball>()
+            .add_system(landing_keys_system)
+            // once the ships are back on the sea, and before the controls are flown
+            .add_system(carrier_start_system.before(PlaneSystem::Controls))
+            .add_system(meatball_system)
+            .add_system_set(
+                SystemSet::new().with_run_criteria(SimTick).with_system(
+                    gear_system
+                        .label(PlaneSystem::Flight)
+                        .after(plane::plane_system)
+                        .before(DamageUpdate),
+                ),
+            );
+    }
+}
+
+/// G raises and lowers the gear, H the hook
+fn landing_keys_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    bindings: Res<GamepadBindings>,
+    recorder: Res<Recorder>,
+    mut controls: ResMut<Controls>,
+) {
+    // the recording sets them on playback
+    if let Recorder::Playing { .. } = *recorder {
+        return;
+    }
+
+    let pressed = |key, button| {
+        keyboard_input.just_pressed(key)
+            || gamepad::just_pressed(&gamepads, &gamepad_buttons, button)
+    };
+    if pressed(KeyCode::G, bindings.gear) {
+        controls.gear = !controls.gear;
+    }
+    if pressed(KeyCode::H, bindings.hook) {
+        controls.hook = !controls.hook;
+    }
+}
+
+/// `cargo run -- --carrier` starts the player on the catapult of their own carrier, each time the
+/// mission starts
+fn carrier_start_system(
+    mut events: EventReader<RestartMission>,
+    options: Res<MissionOptions>,
+    mut controls: ResMut<Controls>,
+    mut player_query: Query<(&Faction, &mut LandingGear), With<Player>>,
+    carriers: Query<(Entity, &Faction), With<FlightDeck>>,
+) {
+    if events.iter().count() == 0 || !options.carrier {
+        return;
+    }
+
+    let (faction, mut gear) = player_query.single_mut();
+    let carrier = match carriers.iter().find(|(_, carrier)| *carrier == faction) {
+        Some((carrier, _)) => carrier,
+        None => {
+            warn!("No carrier of the player's to start on");
+            return;
+        }
+    };
+
+    controls.gear = true;
+    controls.thrust = MINIMUM_THRUST;
+    gear.catapult = Some(Catapult {
+        carrier,
+        travelled: 0.0,
+        speed: 0.0,
+        fired: false,
+    });
+    info!("Starting on the catapult");
+}
+
+/// Where the aircraft would come down: the deck of a carrier it's over, or the ground
+struct Surface<'a> {
+    point: Vec3,
+    normal: Vec3,
+    velocity: Vec3,
+    /// the carrier and its deck, with the aircraft's position in its frame and the bow's direction
+    deck: Option<(Entity, &'a FlightDeck, Vec3, Vec3)>,
+}
+
+/// Puts the player's wheels on the ground and carrier decks, and works the catapult and wires.
+/// Coming down too hard, gear up, off the end of the deck or into the sea is a crash.
+#[allow(clippy::type_complexity)]
+fn gear_system(
+    controls: Res<Controls>,
+    height_map: Res<HeightMap>,
+    mut hits: EventWriter<Hit>,
+    mut query: Query<(Entity, &mut Transform, &mut Player, &mut LandingGear)>,
+    carriers: Query<(Entity, &Transform, &Ship, &FlightDeck), Without<Player>>,
+) {
+    let (entity, mut transform, mut player, mut gear) = query.single_mut();
+    let gear = &mut *gear;
+
+    if let Some(catapult) = &mut gear.catapult {
+        let (_, ship_transform, ship, deck) = match carriers.get(catapult.carrier) {
+            Ok(carrier) => carrier,
+            Err(_) => {
+                gear.catapult = None;
+                return;
+            }
+        };
+
+        // held on the shuttle until the pilot goes to full power
+        if !catapult.fired && controls.thrust >= MAXIMUM_THRUST {
+            catapult.fired = true;
+            info!("Catapult fired");
+        }
+        if catapult.fired {
+            catapult.speed += CATAPULT_ACCELERATION;
+            catapult.travelled += catapult.speed;
+        }
+
+        let forward = ship_transform.rotation * -Vec3::Z;
+        let up = ship_transform.rotation * Vec3::Y;
+        let start = ship_transform.translation
+            + ship_transform.rotation * Vec3::new(0.0, deck.height + GEAR_HEIGHT, -CATAPULT_START);
+        transform.translation = start + forward * catapult.travelled;
+        let position = transform.translation;
+        transform.look_at(position + forward, up);
+        player.velocity = ship.velocity + forward * catapult.speed;
+
+        // off the bow at the end of the stroke
+        gear.on_ground = catapult.travelled < deck.catapult_stroke();
+        if !gear.on_ground {
+            gear.catapult = None;
+        }
+        return;
+    }
+
+    let position = transform.translation;
+    let surface = carriers
+        .iter()
+        .find_map(|(carrier, ship_transform, ship, deck)| {
+            let local = ship_transform.rotation.inverse() * (position - ship_transform.translation);
+            deck.covers(local).then(|| Surface {
+                point: ship_transform.translation
+                    + ship_transform.rotation * Vec3::new(local.x, deck.height, local.z),
+                normal: ship_transform.rotation * Vec3::Y,
+                velocity: ship.velocity,
+                deck: Some((carrier, deck, local, ship_transform.rotation * -Vec3::Z)),
+            })
+        })
+        .unwrap_or_else(|| Surface {
+            point: Vec3::new(position.x, height_map.height(position), position.z),
+            normal: Vec3::Y,
+            velocity: Vec3::ZERO,
+            deck: None,
+        });
+
+    let gap = (position - surface.point).dot(surface.normal) - GEAR_HEIGHT;
+    let relative = player.velocity - surface.velocity;
+    let sink = -relative.dot(surface.normal);
+
+    if let Some((carrier, deck, local, deck_forward)) = surface.deck {
+        // the hook catches the first wire it crosses on its way up the deck
+        if controls.hook && gear.arrested.is_none() && gap < HOOK_REACH {
+            let along = relative.dot(deck_forward);
+            let previous = local.z + along;
+            if let Some(wire) =
+                (0..WIRES).find(|&wire| previous >= deck.wire(wire) && local.z < deck.wire(wire))
+            {
+                gear.arrested = Some(Arrest {
+                    carrier,
+                    deceleration: along * along / (2.0 * ARRESTOR_RUNOUT),
+                });
+                gear.last_wire = Some(wire + 1);
+                info!("Trapped, {} wire", wire + 1);
+            }
+        }
+
+        // the wire holds a stopped aircraft until the throttle comes back to idle
+        if let Some(arrest) = &gear.arrested {
+            let along = (relative.dot(deck_forward) - arrest.deceleration).max(0.0);
+            let normal = surface.normal * relative.dot(surface.normal);
+            player.velocity = surface.velocity + deck_forward * along + normal;
+            if arrest.carrier != carrier || (along == 0.0 && controls.thrust <= MINIMUM_THRUST) {
+                gear.arrested = None;
+            }
+        }
+
+        // taxied onto the shuttle slow enough, the catapult crew hooks the aircraft up
+        let shuttle = Vec3::new(0.0, local.y, -CATAPULT_START);
+        if controls.catapult
+            && gap <= 0.0
+            && relative.length() < HOOKUP_SPEED
+            && local.distance(shuttle) < CATAPULT_REACH
+        {
+            gear.catapult = Some(Catapult {
+                carrier,
+                travelled: 0.0,
+                speed: 0.0,
+                fired: false,
+            });
+            gear.arrested = None;
+            gear.last_wire = None;
+            info!("Hooked up to the catapult");
+        }
+    } else {
+        gear.arrested = None;
+    }
+
+    if gap > 0.0 {
+        gear.on_ground = false;
+        if controls.gear {
+            player.velocity *= 1.0 - GEAR_DRAG;
+        }
+        return;
+    }
+
+    // a level touchdown sinks no further into the surface than it came down in the tick
+    let penetration = -gap;
+    let ditched = surface.deck.is_none() && !height_map.on_land(position);
+    if !controls.gear || sink > MAX_SINK_RATE || penetration > sink.max(0.0) + 1.0 || ditched {
+        info!("Crashed on landing");
+        hits.send(Hit {
+            entity,
+            zone: None,
+            damage: 100.0,
+        });
+        transform.translation += surface.normal * penetration;
+        player.velocity = surface.velocity;
+        return;
+    }
+    gear.on_ground = true;
+
+    // on the wheels: no sinking into the surface, no skidding sideways, and rolling friction
+    transform.translation += surface.normal * penetration;
+    // taken afresh, as a wire may have slowed the aircraft
+    let mut rolling = player.velocity - surface.velocity;
+    if sink > 0.0 {
+        rolling += surface.normal * sink;
+    }
+    let side = transform.local_x();
+    let side = (side - surface.normal * side.dot(surface.normal)).normalize_or_zero();
+    rolling -= side * rolling.dot(side);
+    let friction = if controls.airbrakes {
+        ROLLING_FRICTION + BRAKING
+    } else {
+        ROLLING_FRICTION
+    };
+    let speed = (rolling.length() - friction).max(0.0);
+    player.velocity = surface.velocity + rolling.normalize_or_zero() * speed;
+
+    // wings level on the surface, and the nose can come up but not go through it
+    let mut forward = transform.forward();
+    let into = forward.dot(surface.normal);
+    if into < 0.0 {
+        forward -= surface.normal * into;
+    }
+    let position = transform.translation;
+    transform.look_at(position + forward, surface.normal);
+}
+
+/// Works out where the ball sits on the lens of the nearest of the player's carriers they're
+/// astern of, and how far off the centreline they are
+fn meatball_system(
+    mut meatball: ResMut<Meatball>,
+    player_query: Query<(&Transform, &Faction, &LandingGear), With<Player>>,
+    carriers: Query<(&Transform, &Faction, &FlightDeck), Without<Player>>,
+) {
+    let (transform, faction, gear) = player_query.single();
+    let position = transform.translation;
+
+    *meatball = Meatball::default();
+    if gear.on_ground {
+        return;
+    }
+
+    // the lens is stabilised, so only the ship's heading matters
+    let lens = carriers
+        .iter()
+        .filter(|(_, carrier, _)| *carrier == faction)
+        .filter_map(|(ship_transform, _, deck)| {
+            let forward = (ship_transform.rotation * -Vec3::Z) * Vec3::new(1.0, 0.0, 1.0);
+            let forward = forward.normalize_or_zero();
+            let right = Vec3::new(-forward.z, 0.0, forward.x);
+            let touchdown = ship_transform.translation
+                + ship_transform.rotation * Vec3::new(0.0, deck.height, deck.wire(TARGET_WIRE - 1));
+            let offset = position - touchdown;
+            let astern = -offset.dot(forward);
+            let lateral = offset.dot(right);
+            let visible = astern > 0.0
+                && astern < LENS_RANGE
+                && lateral.abs() < astern * LENS_AZIMUTH_DEGREES.to_radians().tan();
+            visible.then(|| (astern, lateral, offset.y))
+        })
+        .min_by(|(a, _, _), (b, _, _)| a.total_cmp(b));
+
+    if let Some((astern, lateral, height)) = lens {
+        let elevation = height.atan2(astern).to_degrees();
+        meatball.ball = Some((elevation - GLIDESLOPE_DEGREES) / CELL_DEGREES);
+        meatball.lineup = lateral.atan2(astern).to_degrees();
+    }
+}
@@ -2,8 +2,25 @@ use bevy::prelude::*;
 use bevy_atmosphere::*;
 
 mod ai;
+mod autopilot;
+mod benchmark;
+mod damage;
+mod faction;
+mod formation;
+mod gamepad;
+mod ground;
+mod gun;
 mod hud;
+mod landing;
+mod missile;
+mod mission;
+mod mouse_aim;
+mod naval;
 mod plane;
+mod recording;
+mod sim_time;
+mod spatial;
+mod terrain;
 mod utils;
 
 const INITIAL_PLANE_SPEED: f32 = 40.0;
@@ -15,6 +32,23 @@ pub struct Controls {
     roll: f32,
     thrust: f32,
     airbrakes: bool,
+    /// gun trigger held
+    trigger: bool,
+    /// heat seeker launch button held
+    launch: bool,
+    /// radar missile launch button held
+    launch_radar: bool,
+    /// landing gear and arrestor hook lowered
+    gear: bool,
+    hook: bool,
+    /// catapult hookup button held
+    catapult: bool,
+    /// target lock key pressed, waiting for the next tick to act on it
+    lock: bool,
+    /// radio call picked from the menu, waiting for the next tick to give it
+    radio: Option<usize>,
+    /// formation key pressed, waiting for the next tick to change shape
+    formation: bool,
 }
 
 impl Default for Controls {
@@ -25,6 +59,15 @@ impl Default for Controls {
             roll: 0.0,
             thrust: 50.0,
             airbrakes: false,
+            trigger: false,
+            launch: false,
+            launch_radar: false,
+            gear: false,
+            hook: false,
+            catapult: false,
+            lock: false,
+            radio: None,
+            formation: false,
         }
     }
 }
@@ -33,6 +76,8 @@ impl Default for Controls {
 pub struct Player {
     velocity: Vec3,
     stalling: bool,
+    /// the hostile locked with the target key
+    target: Option<Entity>,
 }
 
 impl Default for Player {
@@ -40,13 +85,15 @@ impl Default for Player {
         Player {
             velocity: Vec3::new(0.0, 0.0, -INITIAL_PLANE_SPEED),
             stalling: false,
+            target: None,
         }
     }
 }
 
 #[derive(Component)]
 pub struct AI {
-    health: f32,
+    /// fraction of a full tank
+    fuel: f32,
     velocity: Vec3,
     target: Option<Entity>,
 }
@@ -54,23 +101,22 @@ pub struct AI {
 impl Default for AI {
     fn default() -> Self {
         AI {
-            health: 100.0,
+            fuel: 1.0,
             velocity: Vec3::new(0.0, 0.0, -1.0),
             target: None,
         }
     }
 }
 
-#[derive(Component)]
-pub struct Ally;
-
-#[derive(Component)]
-pub struct Enemy;
-
 #[derive(Component)]
 pub struct Camera3d;
 
 fn main() {
+    if benchmark::requested() {
+        benchmark::run();
+        return;
+    }
+
     App::new()
         .insert_resource(WindowDescriptor {
             title: "Flight Sim".to_string(),
@@ -91,9 +137,25 @@ fn main() {
             dynamic: false,
             sky_radius: 1000000.0,
         })
+        .add_plugin(sim_time::SimTimePlugin)
+        .add_plugin(faction::FactionPlugin)
+        .add_plugin(spatial::SpatialPlugin)
+        .add_plugin(terrain::TerrainPlugin)
+        .add_plugin(mission::MissionPlugin)
+        .add_plugin(ground::GroundPlugin)
+        .add_plugin(naval::NavalPlugin)
+        .add_plugin(gamepad::GamepadPlugin)
         .add_plugin(hud::HUDPlugin)
         .add_plugin(plane::PlanePlugin)
+        .add_plugin(landing::LandingPlugin)
+        .add_plugin(mouse_aim::MouseAimPlugin)
+        .add_plugin(autopilot::AutopilotPlugin)
+        .add_plugin(recording::RecordingPlugin)
         .add_plugin(ai::AIPlugin)
+        .add_plugin(gun::GunPlugin)
+        .add_plugin(missile::MissilePlugin)
+        .add_plugin(damage::DamagePlugin)
+        .add_plugin(formation::FormationPlugin)
         .add_startup_system(setup)
         .run();
 }
@@ -107,6 +169,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
             Transform::from_scale(Vec3::new(10.0, 10.0, 10.0)),
             GlobalTransform::identity(),
         ))
+        .insert(terrain::Cityscape)
         .with_children(|parent| {
             parent.spawn_scene(cityscape);
         });
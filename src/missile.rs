@@ -0,0 +1,858 @@
+use bevy::prelude::*;
+
+use super::ai::{
+    self,
+    orders::WingmanOrder,
+    sensors::{Contacts, Radar},
+    skill::Skill,
+    state::{AIMode, AIState},
+};
+use super::damage::{AircraftDestroyed, DamageUpdate, Hit, WeaponSystem};
+use super::mission::RestartMission;
+use super::plane::{PlaneSystem, GRAVITY};
+use super::sim_time::{SimClock, SimId, SimIds, SimTick};
+use super::spatial::{SpatialEntry, SpatialIndex, SpatialIndexUpdate};
+use super::terrain::HeightMap;
+use super::{Controls, Player, AI};
+
+/// missiles of each kind carried by each aircraft
+const INFRARED_MISSILES: u32 = 2;
+const SEMI_ACTIVE_MISSILES: u32 = 2;
+const ACTIVE_MISSILES: u32 = 4;
+/// ticks between launches from the same aircraft
+const LAUNCH_INTERVAL: u32 = 120;
+/// ticks between an AI's looks at whether it has a shot, staggered between aircraft
+const LAUNCH_CHECK_TICKS: u64 = 15;
+/// AI at least this aggressive take a radar missile shot as soon as it could reach; the rest
+/// hold it until the target couldn't outrun it
+const MAX_RANGE_AGGRESSION: f32 = 0.6;
+/// missiles leave the rail this far ahead of the aircraft's centre
+const RAIL_OFFSET: f32 = 12.0;
+/// a missile that's burned out and slowed below this can't catch anything
+const MIN_SPEED: f32 = 30.0;
+/// ranges tried when working out a launch zone, refined at its edges
+const ZONE_SAMPLES: usize = 24;
+const ZONE_REFINEMENTS: usize = 5;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MissileKind {
+    /// heat seeker, locked before launch and left to guide itself
+    Infrared,
+    /// homes on the launcher's radar reflecting off the target, so the launcher has to keep it
+    /// painted all the way
+    SemiActive,
+    /// flies on the launcher's radar track by datalink, then turns on its own radar at pitbull
+    Active,
+}
+
+/// How a kind of missile flies and finds its target
+struct MissileSpec {
+    /// ticks the motor burns for
+    burn_ticks: u32,
+    /// motor force, in units per tick squared at a mass of 1
+    thrust: f32,
+    launch_mass: f32,
+    /// mass once the motor has burned all its propellant
+    burnout_mass: f32,
+    /// deceleration at a speed of 1 and a mass of 1, growing with the square of speed
+    drag: f32,
+    /// extra drag for each unit per tick squared of lateral acceleration, squared
+    induced_drag: f32,
+    /// lateral acceleration the fins give at a speed of 1, growing with the square of speed
+    lift: f32,
+    /// lateral acceleration the airframe is built for, in g
+    max_g: f32,
+    /// proportional navigation constant
+    navigation: f32,
+    /// rear aspect; head on, a heat seeker only sees a fraction of this
+    seeker_range: f32,
+    /// half angle off the launcher's nose a heat seeker can lock a target in before launch
+    seeker_fov: f32,
+    /// half angle off the missile's axis the seeker can turn to follow the target
+    gimbal: f32,
+    /// fraction of the seeker's range it has head on
+    head_on_range: f32,
+    /// an active missile turns its own radar on this far from where the datalink puts the target
+    pitbull_range: f32,
+    /// the fuze doesn't arm until the missile is clear of the launcher
+    arm_ticks: u32,
+    /// the warhead goes off when the target passes this close
+    fuse_range: f32,
+    damage: f32,
+    flight_ticks: u32,
+}
+
+const INFRARED: MissileSpec = MissileSpec {
+    burn_ticks: 90,
+    thrust: 1.2,
+    launch_mass: 1.0,
+    burnout_mass: 0.6,
+    drag: 0.000008,
+    induced_drag: 0.015,
+    lift: 0.0003,
+    max_g: 40.0,
+    navigation: 4.0,
+    seeker_range: 6000.0,
+    seeker_fov: 0.5,
+    gimbal: 1.0,
+    head_on_range: 0.5,
+    pitbull_range: 0.0,
+    arm_ticks: 8,
+    fuse_range: 25.0,
+    damage: 60.0,
+    flight_ticks: 400,
+};
+
+const SEMI_ACTIVE: MissileSpec = MissileSpec {
+    burn_ticks: 50,
+    thrust: 4.5,
+    launch_mass: 1.4,
+    burnout_mass: 0.9,
+    drag: 0.00014,
+    induced_drag: 0.02,
+    lift: 0.0002,
+    max_g: 30.0,
+    navigation: 3.0,
+    // the receiver hears the reflections as far out as the launcher's radar reaches
+    seeker_range: 15000.0,
+    seeker_fov: 0.0,
+    gimbal: 1.0,
+    head_on_range: 1.0,
+    pitbull_range: 0.0,
+    arm_ticks: 10,
+    fuse_range: 30.0,
+    damage: 70.0,
+    flight_ticks: 300,
+};
+
+const ACTIVE: MissileSpec = MissileSpec {
+    burn_ticks: 60,
+    thrust: 5.0,
+    launch_mass: 1.5,
+    burnout_mass: 0.9,
+    drag: 0.00012,
+    induced_drag: 0.02,
+    lift: 0.0002,
+    max_g: 30.0,
+    navigation: 3.0,
+    seeker_range: 4000.0,
+    seeker_fov: 0.0,
+    gimbal: 0.9,
+    head_on_range: 1.0,
+    pitbull_range: 4000.0,
+    arm_ticks: 10,
+    fuse_range: 30.0,
+    damage: 70.0,
+    flight_ticks: 400,
+};
+
+impl MissileKind {
+    fn spec(self) -> &'static MissileSpec {
+        match self {
+            MissileKind::Infrared => &INFRARED,
+            MissileKind::SemiActive => &SEMI_ACTIVE,
+            MissileKind::Active => &ACTIVE,
+        }
+    }
+
+    /// Whether the seeker can see the target within `cone` of `boresight`; a heat seeker sees
+    /// furthest looking up the target's tailpipe
+    fn sees(
+        self,
+        position: Vec3,
+        boresight: Vec3,
+        cone: f32,
+        target_position: Vec3,
+        target_velocity: Vec3,
+    ) -> bool {
+        let spec = self.spec();
+        let offset = target_position - position;
+        let line_of_sight = offset.normalize_or_zero();
+        if boresight.angle_between(line_of_sight) > cone {
+            return false;
+        }
+        let aspect = target_velocity.normalize_or_zero().dot(line_of_sight);
+        let range = spec.seeker_range
+            * (spec.head_on_range + (1.0 - spec.head_on_range) * (aspect + 1.0) / 2.0);
+        offset.length() < range
+    }
+
+    /// Whether one can go at `target` from an aircraft at `position` pointing along `forward`: a
+    /// heat seeker has to see it, a radar missile needs the aircraft's radar on it
+    fn can_launch(
+        self,
+        position: Vec3,
+        forward: Vec3,
+        radar: Option<&Radar>,
+        target: &SpatialEntry,
+    ) -> bool {
+        match self {
+            MissileKind::Infrared => self.sees(
+                position,
+                forward,
+                self.spec().seeker_fov,
+                target.position,
+                target.velocity,
+            ),
+            MissileKind::SemiActive | MissileKind::Active => {
+                radar.is_some_and(|radar| radar.tracks(position, forward, target))
+            }
+        }
+    }
+}
+
+impl MissileSpec {
+    fn mass(&self, ticks: u32) -> f32 {
+        let burned = (ticks as f32 / self.burn_ticks as f32).min(1.0);
+        self.launch_mass - (self.launch_mass - self.burnout_mass) * burned
+    }
+}
+
+/// Where a missile is and where it's going, stepped the same way in flight and in a flyout
+#[derive(Clone, Copy)]
+struct Kinematics {
+    position: Vec3,
+    velocity: Vec3,
+    ticks: u32,
+}
+
+impl Kinematics {
+    /// Flies one tick: the motor pushes while it burns, drag grows with speed and with how hard
+    /// the missile is pulling, and it can pull no harder than the fins and airframe allow
+    fn step(&mut self, spec: &MissileSpec, command: Vec3) {
+        let mass = spec.mass(self.ticks);
+        let speed = self.velocity.length();
+        let direction = self.velocity.normalize_or_zero();
+
+        let lateral = command - direction * command.dot(direction);
+        let limit = (spec.max_g * -GRAVITY).min(spec.lift * speed * speed);
+        let lateral = lateral.clamp_length_max(limit);
+
+        let thrust = if self.ticks < spec.burn_ticks {
+            spec.thrust / mass
+        } else {
+            0.0
+        };
+        let drag =
+            (spec.drag * speed * speed + spec.induced_drag * lateral.length_squared()) / mass;
+
+        self.velocity += direction * (thrust - drag) + lateral + Vec3::Y * GRAVITY;
+        self.position += self.velocity;
+        self.ticks += 1;
+    }
+
+    /// Proportional navigation: turn at a multiple of the rate the line of sight to the target
+    /// turns at, holding the missile up against gravity while it's at it
+    fn guidance(&self, spec: &MissileSpec, target_position: Vec3, target_velocity: Vec3) -> Vec3 {
+        let offset = target_position - self.position;
+        let closing = target_velocity - self.velocity;
+        let rotation = offset.cross(closing) / offset.length_squared().max(1.0);
+        spec.navigation * rotation.cross(self.velocity) - Vec3::Y * GRAVITY
+    }
+
+    /// Steps the missile, steering for `aim` if it has anything to steer for
+    fn advance(&mut self, spec: &MissileSpec, aim: Option<(Vec3, Vec3)>) {
+        let command = aim.map_or(Vec3::ZERO, |(position, velocity)| {
+            self.guidance(spec, position, velocity)
+        });
+        self.step(spec, command);
+    }
+
+    /// How close the missile passed the target over the tick it's just flown from `start`, with
+    /// the target where it was at the start of the tick
+    fn closest_approach(&self, start: Vec3, target_position: Vec3, target_velocity: Vec3) -> f32 {
+        let offset = target_position - start;
+        let closing = target_velocity - self.velocity;
+        let time = if closing.length_squared() > 0.0 {
+            (-offset.dot(closing) / closing.length_squared()).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        (offset + closing * time).length()
+    }
+
+    fn armed(&self, spec: &MissileSpec) -> bool {
+        self.ticks > spec.arm_ticks
+    }
+
+    /// out of time, or burned out and too slow to catch anything
+    fn spent(&self, spec: &MissileSpec) -> bool {
+        self.ticks >= spec.flight_ticks
+            || (self.ticks > spec.burn_ticks && self.velocity.length() < MIN_SPEED)
+    }
+}
+
+/// Flies a missile from the launcher against a target holding its course and speed, and says
+/// whether it would get close enough to go off. A radar missile is taken to keep the launcher's
+/// support all the way, so only its own seeker can lose the target.
+fn flyout(
+    kind: MissileKind,
+    position: Vec3,
+    velocity: Vec3,
+    mut target_position: Vec3,
+    target_velocity: Vec3,
+) -> bool {
+    let spec = kind.spec();
+    let mut missile = Kinematics {
+        position,
+        velocity,
+        ticks: 0,
+    };
+
+    while !missile.spent(spec) {
+        let seeking = match kind {
+            MissileKind::Infrared => true,
+            MissileKind::SemiActive => false,
+            MissileKind::Active => missile.position.distance(target_position) < spec.pitbull_range,
+        };
+        if seeking
+            && !kind.sees(
+                missile.position,
+                missile.velocity.normalize_or_zero(),
+                spec.gimbal,
+                target_position,
+                target_velocity,
+            )
+        {
+            return false;
+        }
+
+        let start = missile.position;
+        missile.advance(spec, Some((target_position, target_velocity)));
+        if missile.armed(spec)
+            && missile.closest_approach(start, target_position, target_velocity) < spec.fuse_range
+        {
+            return true;
+        }
+        target_position += target_velocity;
+    }
+    false
+}
+
+/// The missiles an aircraft carries
+#[derive(Component)]
+pub struct MissileRack {
+    pub infrared: u32,
+    pub semi_active: u32,
+    pub active: u32,
+    /// ticks until the next can go
+    cooldown_ticks: u32,
+}
+
+impl Default for MissileRack {
+    fn default() -> Self {
+        MissileRack {
+            infrared: INFRARED_MISSILES,
+            semi_active: SEMI_ACTIVE_MISSILES,
+            active: ACTIVE_MISSILES,
+            cooldown_ticks: 0,
+        }
+    }
+}
+
+impl MissileRack {
+    fn loaded(&mut self, kind: MissileKind) -> &mut u32 {
+        match kind {
+            MissileKind::Infrared => &mut self.infrared,
+            MissileKind::SemiActive => &mut self.semi_active,
+            MissileKind::Active => &mut self.active,
+        }
+    }
+
+    /// the radar missile that goes next, fire and forget first
+    fn radar_missile(&self) -> Option<MissileKind> {
+        if self.active > 0 {
+            Some(MissileKind::Active)
+        } else if self.semi_active > 0 {
+            Some(MissileKind::SemiActive)
+        } else {
+            None
+        }
+    }
+}
+
+/// What a missile steers for
+#[derive(Clone, Copy)]
+enum Guidance {
+    /// its own seeker's lock on the target
+    Seeker,
+    /// the launcher's radar reflecting off the target
+    Illuminated,
+    /// where the launcher's radar last put the target, dead reckoned since the datalink lost it
+    Datalink { position: Vec3, velocity: Vec3 },
+}
+
+/// A missile in flight
+#[derive(Component)]
+pub struct Missile {
+    kind: MissileKind,
+    /// the aircraft whose radar a radar missile relies on
+    shooter: Entity,
+    /// dropped once the missile loses it, after which it flies on unguided
+    target: Option<Entity>,
+    guidance: Guidance,
+    kinematics: Kinematics,
+}
+
+/// The player's shots at the locked target, for the HUD
+#[derive(Default)]
+pub struct LaunchZone {
+    pub infrared: Option<Shot>,
+    /// with the radar missile that would go next
+    pub radar: Option<Shot>,
+}
+
+#[derive(Clone, Copy)]
+pub struct Shot {
+    pub range: f32,
+    /// the launch acceptability region along the line of sight, as it stands; None if the
+    /// missile couldn't hit from any range
+    pub zone: Option<(f32, f32)>,
+    /// the furthest the missile would still catch the target if it turned and ran
+    pub no_escape: Option<f32>,
+}
+
+impl Shot {
+    /// Works out the zone along the line of sight to `target`, out to `limit`, by flying the
+    /// missile out against the target at a spread of ranges, flying on as it is and running
+    /// straight away
+    fn new(
+        kind: MissileKind,
+        position: Vec3,
+        velocity: Vec3,
+        target: &SpatialEntry,
+        limit: f32,
+    ) -> Self {
+        let offset = target.position - position;
+        let line_of_sight = offset.normalize_or_zero();
+        let hits = |target_velocity: Vec3| {
+            move |range: f32| {
+                flyout(
+                    kind,
+                    position,
+                    velocity,
+                    position + line_of_sight * range,
+                    target_velocity,
+                )
+            }
+        };
+        let running = line_of_sight * target.velocity.length();
+
+        Shot {
+            range: offset.length(),
+            zone: zone_edges(limit, hits(target.velocity)),
+            no_escape: zone_edges(limit, hits(running)).map(|(_, max)| max),
+        }
+    }
+
+    pub fn in_zone(&self) -> bool {
+        self.zone
+            .is_some_and(|(min, max)| (min..=max).contains(&self.range))
+    }
+}
+
+struct MissileModel {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+pub struct MissilePlugin;
+
+impl Plugin for MissilePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LaunchZone>()
+            .add_startup_system(setup)
+            .add_system_to_stage(CoreStage::PreUpdate, restart_system)
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(SimTick)
+                    // once everything has moved, so missiles leave from where the rails are now,
+                    // and after the guns, so everything fired is numbered in the same order
+                    .with_system(
+                        player_launch_system
+                            .after(PlaneSystem::Flight)
+                            .after(WeaponSystem::Guns)
+                            .before(missile_system),
+                    )
+                    .with_system(
+                        ai_launch_system
+                            .after(ai::ai_system)
+                            .after(player_launch_system)
+                            .before(missile_system),
+                    )
+                    .with_system(
+                        missile_system
+                            .label(WeaponSystem::Missiles)
+                            .after(SpatialIndexUpdate)
+                            .after(WeaponSystem::Guns)
+                            .before(DamageUpdate),
+                    )
+                    .with_system(launch_zone_system.after(PlaneSystem::Flight))
+                    .with_system(destroyed_system.after(DamageUpdate)),
+            );
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(MissileModel {
+        mesh: meshes.add(Mesh::from(shape::Box::new(0.6, 0.6, 6.0))),
+        material: materials.add(Color::rgb(0.85, 0.85, 0.8).into()),
+    });
+}
+
+/// Clears away the missiles in flight
+fn restart_system(
+    mut commands: Commands,
+    mut events: EventReader<RestartMission>,
+    missiles: Query<Entity, With<Missile>>,
+) {
+    if events.iter().count() == 0 {
+        return;
+    }
+
+    for entity in missiles.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn launch(
+    commands: &mut Commands,
+    ids: &mut SimIds,
+    model: &MissileModel,
+    kind: MissileKind,
+    shooter: Entity,
+    transform: &Transform,
+    velocity: Vec3,
+    target: &SpatialEntry,
+) {
+    let position = transform.translation + transform.forward() * RAIL_OFFSET;
+    let guidance = match kind {
+        MissileKind::Infrared => Guidance::Seeker,
+        MissileKind::SemiActive => Guidance::Illuminated,
+        MissileKind::Active => Guidance::Datalink {
+            position: target.position,
+            velocity: target.velocity,
+        },
+    };
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: model.mesh.clone(),
+            material: model.material.clone(),
+            transform: Transform::from_translation(position).with_rotation(transform.rotation),
+            ..default()
+        })
+        .insert(Missile {
+            kind,
+            shooter,
+            target: Some(target.entity),
+            guidance,
+            kinematics: Kinematics {
+                position,
+                velocity,
+                ticks: 0,
+            },
+        })
+        .insert(ids.allocate());
+}
+
+/// Fires a heat seeker or a radar missile at the locked target while its button's held, if the
+/// seeker can see it or the radar has it
+fn player_launch_system(
+    mut commands: Commands,
+    controls: Res<Controls>,
+    index: Res<SpatialIndex>,
+    model: Res<MissileModel>,
+    mut ids: ResMut<SimIds>,
+    mut query: Query<(Entity, &Transform, &Player, &Radar, &mut MissileRack)>,
+) {
+    let (entity, transform, player, radar, mut rack) = query.single_mut();
+    rack.cooldown_ticks = rack.cooldown_ticks.saturating_sub(1);
+
+    let kind = if controls.launch && rack.infrared > 0 {
+        MissileKind::Infrared
+    } else if controls.launch_radar {
+        match rack.radar_missile() {
+            Some(kind) => kind,
+            None => return,
+        }
+    } else {
+        return;
+    };
+    if rack.cooldown_ticks > 0 {
+        return;
+    }
+
+    let target = match player.target.and_then(|target| index.get(target)) {
+        Some(target) => target,
+        None => return,
+    };
+    if !kind.can_launch(
+        transform.translation,
+        transform.forward(),
+        Some(radar),
+        target,
+    ) {
+        return;
+    }
+
+    launch(
+        &mut commands,
+        &mut ids,
+        &model,
+        kind,
+        entity,
+        transform,
+        player.velocity,
+        target,
+    );
+    *rack.loaded(kind) -= 1;
+    rack.cooldown_ticks = LAUNCH_INTERVAL;
+}
+
+/// Every so often, each AI in a fight looks at whether a missile would reach its target from
+/// here, and fires one if it would, radar missiles before heat seekers
+#[allow(clippy::type_complexity)]
+fn ai_launch_system(
+    mut commands: Commands,
+    clock: Res<SimClock>,
+    model: Res<MissileModel>,
+    mut ids: ResMut<SimIds>,
+    mut query: Query<(
+        Entity,
+        &SimId,
+        &Transform,
+        &AI,
+        &AIState,
+        &Contacts,
+        &Skill,
+        Option<&Radar>,
+        &mut MissileRack,
+        Option<&WingmanOrder>,
+    )>,
+) {
+    // in `SimId` order, so the missiles are numbered in the same order every run
+    let mut aircraft: Vec<_> = query.iter_mut().collect();
+    aircraft.sort_unstable_by_key(|(_, id, ..)| **id);
+
+    for (entity, id, transform, ai, state, contacts, skill, radar, mut rack, order) in aircraft {
+        rack.cooldown_ticks = rack.cooldown_ticks.saturating_sub(1);
+        if rack.cooldown_ticks > 0
+            || !(clock.tick + id.0 as u64).is_multiple_of(LAUNCH_CHECK_TICKS)
+            || !matches!(state.mode, AIMode::Engage | AIMode::Intercept)
+            || order.is_some_and(|order| order.0.holds_fire())
+        {
+            continue;
+        }
+
+        let target = match ai.target.and_then(|target| contacts.get(target)) {
+            Some(target) => target,
+            None => continue,
+        };
+        let position = transform.translation;
+        let forward = transform.forward();
+
+        let kind = [rack.radar_missile(), Some(MissileKind::Infrared)]
+            .into_iter()
+            .flatten()
+            .filter(|&kind| *rack.loaded(kind) > 0)
+            .find(|&kind| {
+                if !kind.can_launch(position, forward, radar, target) {
+                    return false;
+                }
+                // the cautious hold a radar missile until the target couldn't run from it
+                let target_velocity = match kind {
+                    MissileKind::SemiActive | MissileKind::Active
+                        if skill.aggression < MAX_RANGE_AGGRESSION =>
+                    {
+                        (target.position - position).normalize_or_zero() * target.velocity.length()
+                    }
+                    _ => target.velocity,
+                };
+                flyout(
+                    kind,
+                    position,
+                    ai.velocity,
+                    target.position,
+                    target_velocity,
+                )
+            });
+        let kind = match kind {
+            Some(kind) => kind,
+            None => continue,
+        };
+
+        launch(
+            &mut commands,
+            &mut ids,
+            &model,
+            kind,
+            entity,
+            transform,
+            ai.velocity,
+            target,
+        );
+        *rack.loaded(kind) -= 1;
+        rack.cooldown_ticks = LAUNCH_INTERVAL;
+    }
+}
+
+/// Flies each missile, guiding it on whatever it has of the target, and sets it off when the
+/// target passes inside the fuze's range. A missile that loses the target doesn't get it back.
+fn missile_system(
+    mut commands: Commands,
+    index: Res<SpatialIndex>,
+    height_map: Res<HeightMap>,
+    mut query: Query<(Entity, &SimId, &mut Transform, &mut Missile)>,
+    mut hits: EventWriter<Hit>,
+) {
+    // in launch order, so their hits come in the same order every run
+    let mut missiles: Vec<_> = query.iter_mut().collect();
+    missiles.sort_unstable_by_key(|(_, id, ..)| **id);
+
+    for (entity, _, mut transform, mut missile) in missiles {
+        let kind = missile.kind;
+        let spec = kind.spec();
+        let start = missile.kinematics.position;
+        let boresight = missile.kinematics.velocity.normalize_or_zero();
+
+        let target = missile.target.and_then(|target| index.get(target));
+        // within the seeker's gimbal
+        let seen = target.filter(|target| {
+            kind.sees(
+                start,
+                boresight,
+                spec.gimbal,
+                target.position,
+                target.velocity,
+            )
+        });
+        // still held by the launcher's radar, to illuminate or send over the datalink
+        let supported = target.filter(|target| {
+            index.get(missile.shooter).is_some_and(|shooter| {
+                shooter
+                    .radar
+                    .is_some_and(|radar| radar.tracks(shooter.position, shooter.forward, target))
+            })
+        });
+
+        let state = |target: &SpatialEntry| (target.position, target.velocity);
+        let aim = match (missile.guidance, target) {
+            (_, None) => None,
+            (Guidance::Seeker, _) => seen.map(state),
+            (Guidance::Illuminated, _) => seen.and(supported).map(state),
+            (Guidance::Datalink { position, velocity }, Some(_)) => {
+                let (position, velocity) = supported.map_or((position + velocity, velocity), state);
+                // pitbull: from here the missile's own radar takes over
+                missile.guidance = if position.distance(start) < spec.pitbull_range {
+                    Guidance::Seeker
+                } else {
+                    Guidance::Datalink { position, velocity }
+                };
+                Some((position, velocity))
+            }
+        };
+        if aim.is_none() {
+            missile.target = None;
+        }
+
+        missile.kinematics.advance(spec, aim);
+        if let Some(target) = target.filter(|_| aim.is_some()) {
+            if missile.kinematics.armed(spec)
+                && missile
+                    .kinematics
+                    .closest_approach(start, target.position, target.velocity)
+                    < spec.fuse_range
+            {
+                hits.send(Hit {
+                    entity: target.entity,
+                    zone: None,
+                    damage: spec.damage,
+                });
+                commands.entity(entity).despawn();
+                continue;
+            }
+        }
+
+        let Kinematics {
+            position, velocity, ..
+        } = missile.kinematics;
+        transform.translation = position;
+        transform.look_at(position + velocity, Vec3::Y);
+
+        if missile.kinematics.spent(spec) || position.y < height_map.height(position) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Works out the player's launch zones against the locked target, for a heat seeker out to its
+/// seeker's range and for the next radar missile out to the radar's
+fn launch_zone_system(
+    index: Res<SpatialIndex>,
+    mut zone: ResMut<LaunchZone>,
+    query: Query<(&Transform, &Player, &Radar, &MissileRack)>,
+) {
+    let (transform, player, radar, rack) = query.single();
+    let target = player.target.and_then(|target| index.get(target));
+    let position = transform.translation;
+
+    zone.infrared = target.map(|target| {
+        Shot::new(
+            MissileKind::Infrared,
+            position,
+            player.velocity,
+            target,
+            INFRARED.seeker_range,
+        )
+    });
+    zone.radar = target
+        .zip(rack.radar_missile())
+        .map(|(target, kind)| Shot::new(kind, position, player.velocity, target, radar.range));
+}
+
+/// The nearest and furthest ranges `hits` is true at, out to `limit`, assuming it's true
+/// everywhere between them
+fn zone_edges(limit: f32, hits: impl Fn(f32) -> bool) -> Option<(f32, f32)> {
+    let step = limit / ZONE_SAMPLES as f32;
+    let first = (1..=ZONE_SAMPLES).find(|&sample| hits(sample as f32 * step))?;
+    let last = (first..=ZONE_SAMPLES)
+        .take_while(|&sample| hits(sample as f32 * step))
+        .last()
+        .unwrap_or(first);
+
+    // bisect each edge between the last range that missed and the first that hit
+    let refine = |mut hit: f32, mut miss: f32| {
+        for _ in 0..ZONE_REFINEMENTS {
+            let middle = (hit + miss) / 2.0;
+            if hits(middle) {
+                hit = middle;
+            } else {
+                miss = middle;
+            }
+        }
+        hit
+    };
+    let min = refine(first as f32 * step, (first - 1) as f32 * step);
+    let max = if last == ZONE_SAMPLES {
+        limit
+    } else {
+        refine(last as f32 * step, (last + 1) as f32 * step)
+    };
+    Some((min, max))
+}
+
+/// Missiles guiding on something shot down fly on unguided
+fn destroyed_system(mut events: EventReader<AircraftDestroyed>, mut query: Query<&mut Missile>) {
+    for event in events.iter() {
+        for mut missile in query.iter_mut() {
+            if missile.target == Some(event.entity) {
+                missile.target = None;
+            }
+        }
+    }
+}
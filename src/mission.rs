@@ -0,0 +1,126 @@
+use std::fs;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::ground::GroundKind;
+use super::naval::ShipKind;
+
+const MISSION_PATH: &str = "assets/config/mission.ron";
+
+/// Sent to clear the fight away and start the mission again from the beginning, which it also
+/// is at startup
+pub struct RestartMission;
+
+/// Puts the mission back as it started, one part after another, so everything in it is spawned
+/// and numbered in the same order every time
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MissionRestart {
+    Clock,
+    Player,
+    Aircraft,
+    Ground,
+    Naval,
+}
+
+#[derive(Deserialize)]
+pub struct GroundPlacement {
+    pub kind: GroundKind,
+    pub faction: String,
+    /// x and z; the unit sits on whatever is there
+    pub position: (f32, f32),
+    /// degrees clockwise from north, along -z
+    #[serde(default)]
+    pub heading: f32,
+    /// points a vehicle drives round, in order, starting from its position
+    #[serde(default)]
+    pub route: Vec<(f32, f32)>,
+}
+
+#[derive(Deserialize)]
+pub struct NavalPlacement {
+    pub kind: ShipKind,
+    pub faction: String,
+    /// x and z
+    pub position: (f32, f32),
+    /// degrees clockwise from north, along -z
+    #[serde(default)]
+    pub heading: f32,
+    /// in units per tick
+    #[serde(default)]
+    pub speed: f32,
+    /// points the ship steams round, in order, starting from its position
+    #[serde(default)]
+    pub route: Vec<(f32, f32)>,
+}
+
+/// Everything placed in the world before the fight starts, other than the aircraft
+#[derive(Default, Deserialize)]
+pub struct Mission {
+    #[serde(default)]
+    pub ground: Vec<GroundPlacement>,
+    #[serde(default)]
+    pub naval: Vec<NavalPlacement>,
+}
+
+impl Mission {
+    pub fn load() -> Self {
+        match fs::read_to_string(MISSION_PATH)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| ron::from_str(&contents).map_err(|err| err.to_string()))
+        {
+            Ok(mission) => mission,
+            Err(err) => {
+                error!("Could not load {}: {}", MISSION_PATH, err);
+                Mission::default()
+            }
+        }
+    }
+}
+
+/// Command line options that change how the mission starts, which a recording is replayed with
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct MissionOptions {
+    /// `--carrier` starts the player on the catapult of their carrier
+    pub carrier: bool,
+    /// `--stress <count>` spawns `count` AI aircraft, split between the factions
+    pub stress: Option<usize>,
+}
+
+impl MissionOptions {
+    pub fn from_args() -> Self {
+        let args: Vec<String> = std::env::args().collect();
+        let stress = args
+            .iter()
+            .position(|arg| arg == "--stress")
+            .and_then(
+                |index| match args.get(index + 1).map(|count| count.parse()) {
+                    Some(Ok(count)) => Some(count),
+                    _ => {
+                        error!("--stress needs an aircraft count");
+                        None
+                    }
+                },
+            );
+
+        MissionOptions {
+            carrier: args.iter().any(|arg| arg == "--carrier"),
+            stress,
+        }
+    }
+}
+
+pub struct MissionPlugin;
+
+impl Plugin for MissionPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Mission::load())
+            .insert_resource(MissionOptions::from_args())
+            .add_event::<RestartMission>()
+            .add_startup_system(start_system);
+    }
+}
+
+fn start_system(mut restart: EventWriter<RestartMission>) {
+    restart.send(RestartMission);
+}
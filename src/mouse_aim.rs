@@ -0,0 +1,163 @@
+use bevy::{input::mouse::MouseMotion, prelude::*};
+
+use super::plane::PlaneSystem;
+use super::sim_time::SimTick;
+use super::utils;
+use super::{Camera3d, Controls, Player};
+
+const MOUSE_SENSITIVITY: f32 = 0.002;
+/// how far the aim point may be raised or lowered from the horizon, in radians
+const MAX_AIM_ELEVATION: f32 = 1.4;
+
+const PITCH_GAIN: f32 = 3.0;
+const YAW_GAIN: f32 = 2.0;
+const ROLL_GAIN: f32 = 1.5;
+const WINGS_LEVEL_GAIN: f32 = 2.0;
+/// below this angle off the nose the instructor levels the wings instead of banking
+const BANK_ANGLE_THRESHOLD: f32 = 0.15;
+
+const CAMERA_Y: f32 = 5.0;
+const CAMERA_Z: f32 = 20.0;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ControlScheme {
+    /// keyboard, stick and HOTAS drive the control surfaces directly
+    Direct,
+    /// the mouse steers an aim point and the instructor flies the plane towards it
+    MouseAim,
+}
+
+pub struct MouseAim {
+    /// world space direction of the aim point from the player
+    pub direction: Vec3,
+}
+
+impl Default for MouseAim {
+    fn default() -> Self {
+        MouseAim {
+            direction: -Vec3::Z,
+        }
+    }
+}
+
+pub struct MouseAimPlugin;
+
+impl Plugin for MouseAimPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ControlScheme::Direct)
+            .insert_resource(MouseAim::default())
+            .add_system(control_scheme_system)
+            .add_system(aim_system.after(control_scheme_system))
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(SimTick)
+                    .with_system(
+                        instructor_system
+                            .after(PlaneSystem::Controls)
+                            .before(PlaneSystem::Autopilot),
+                    )
+                    .with_system(camera_system.after(PlaneSystem::Flight)),
+            );
+    }
+}
+
+fn control_scheme_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut windows: ResMut<Windows>,
+    mut scheme: ResMut<ControlScheme>,
+    mut aim: ResMut<MouseAim>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::M) {
+        return;
+    }
+
+    *scheme = match *scheme {
+        ControlScheme::Direct => ControlScheme::MouseAim,
+        ControlScheme::MouseAim => ControlScheme::Direct,
+    };
+
+    // start aiming wherever the plane is pointing
+    aim.direction = player_query.single().forward();
+
+    if let Some(window) = windows.get_primary_mut() {
+        let captured = *scheme == ControlScheme::MouseAim;
+        window.set_cursor_lock_mode(captured);
+        window.set_cursor_visibility(!captured);
+    }
+}
+
+fn aim_system(
+    scheme: Res<ControlScheme>,
+    mut mouse_motion: EventReader<MouseMotion>,
+    mut aim: ResMut<MouseAim>,
+) {
+    let delta: Vec2 = mouse_motion.iter().map(|motion| &motion.delta).sum();
+
+    if *scheme != ControlScheme::MouseAim || delta == Vec2::ZERO {
+        return;
+    }
+
+    let heading = Quat::from_rotation_y(-delta.x * MOUSE_SENSITIVITY);
+    let elevation = utils::clamp(
+        aim.direction.y.asin() - delta.y * MOUSE_SENSITIVITY,
+        -MAX_AIM_ELEVATION,
+        MAX_AIM_ELEVATION,
+    );
+
+    let horizontal = (heading * aim.direction * Vec3::new(1.0, 0.0, 1.0)).normalize_or_zero();
+    aim.direction = (horizontal * elevation.cos() + Vec3::Y * elevation.sin()).normalize();
+}
+
+/// The virtual instructor: turns the aim direction into stick and rudder commands
+fn instructor_system(
+    scheme: Res<ControlScheme>,
+    aim: Res<MouseAim>,
+    mut controls: ResMut<Controls>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    if *scheme != ControlScheme::MouseAim {
+        return;
+    }
+
+    let transform = player_query.single();
+
+    // in the plane's frame forwards is -Z, up is +Y and the right wing is +X
+    let local = transform.rotation.inverse() * aim.direction;
+    let angle_off = local.angle_between(-Vec3::Z);
+
+    let pitch = local.y.atan2(-local.z) * PITCH_GAIN;
+    let yaw = -local.x.atan2(-local.z) * YAW_GAIN;
+
+    // bank towards the aim point when it is well off the nose, otherwise fly wings level
+    let bank = -local.x.atan2(local.y) * ROLL_GAIN;
+    let wings_level = -transform.local_x().y * WINGS_LEVEL_GAIN;
+    let roll = utils::lerp(
+        wings_level,
+        bank,
+        utils::clamp(angle_off / BANK_ANGLE_THRESHOLD, 0.0, 1.0),
+    );
+
+    controls.pitch = utils::clamp(pitch, -1.0, 1.0);
+    controls.yaw = utils::clamp(yaw, -1.0, 1.0);
+    controls.roll = utils::clamp(roll, -1.0, 1.0);
+}
+
+/// Chase camera that looks along the aim direction rather than the nose
+fn camera_system(
+    scheme: Res<ControlScheme>,
+    aim: Res<MouseAim>,
+    player_query: Query<&Transform, With<Player>>,
+    mut camera_query: Query<&mut Transform, (With<Camera3d>, Without<Player>)>,
+) {
+    if *scheme != ControlScheme::MouseAim {
+        return;
+    }
+
+    let player = player_query.single();
+    let mut camera = camera_query.single_mut();
+
+    camera.translation =
+        player.translation - aim.direction * CAMERA_Z + Vec3::new(0.0, CAMERA_Y, 0.0);
+    camera.look_at(player.translation + aim.direction * 100.0, Vec3::Y);
+}
@@ -0,0 +1,344 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use super::ai::sensors::Radar;
+use super::damage::{DamageUpdate, Hit, SurfaceDestroyed};
+use super::faction::{Faction, Factions};
+use super::ground::SamSite;
+use super::landing::FlightDeck;
+use super::mission::{Mission, MissionRestart, RestartMission};
+use super::plane::PlaneSystem;
+use super::sim_time::{SimClock, SimIds, SimTick};
+use super::spatial::SpatialIndexUpdate;
+use super::utils;
+
+/// just under the city's ground, which hides it where they overlap
+const WATER_LEVEL: f32 = -1.0;
+const WATER_SIZE: f32 = 200000.0;
+/// change in speed per tick, in units per tick
+const ACCELERATION: f32 = 0.004;
+/// radians per tick at full rudder, for each unit per tick of speed; a ship stopped can't turn
+const RUDDER_RATE: f32 = 0.0006;
+/// heading error, in radians, the rudder is put hard over at
+const FULL_RUDDER_ERROR: f32 = 0.5;
+/// radians of heel outwards per radian per tick of turn
+const HEEL: f32 = 20.0;
+/// a ship this close to a point on its route turns for the next
+const ARRIVAL_DISTANCE: f32 = 300.0;
+const SWELL_PERIOD_TICKS: f32 = 600.0;
+/// how far a ship of the reference length rises and falls on the swell, and how far it pitches
+/// and rolls; longer ships ride it more steadily
+const SWELL_HEAVE: f32 = 2.0;
+const SWELL_PITCH: f32 = 0.01;
+const SWELL_ROLL: f32 = 0.03;
+const SWELL_REFERENCE_LENGTH: f32 = 150.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+pub enum ShipKind {
+    /// its deck pitches, rolls and heaves with the ship
+    Carrier,
+    /// carries a SAM battery
+    Destroyer,
+    Cargo,
+}
+
+impl ShipKind {
+    fn health(self) -> f32 {
+        match self {
+            ShipKind::Carrier => 1000.0,
+            ShipKind::Destroyer => 400.0,
+            ShipKind::Cargo => 250.0,
+        }
+    }
+
+    /// width, height above the water and length of the hull
+    fn size(self) -> Vec3 {
+        match self {
+            ShipKind::Carrier => Vec3::new(75.0, 20.0, 330.0),
+            ShipKind::Destroyer => Vec3::new(20.0, 10.0, 155.0),
+            ShipKind::Cargo => Vec3::new(30.0, 12.0, 200.0),
+        }
+    }
+
+    /// in units per tick
+    fn max_speed(self) -> f32 {
+        match self {
+            ShipKind::Carrier => 1.5,
+            ShipKind::Destroyer => 1.8,
+            ShipKind::Cargo => 1.0,
+        }
+    }
+
+    fn radar(self) -> Option<Radar> {
+        match self {
+            ShipKind::Carrier => Some(Radar {
+                range: 20000.0,
+                cone: std::f32::consts::PI,
+            }),
+            ShipKind::Destroyer => Some(SamSite::RADAR),
+            ShipKind::Cargo => None,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Ship {
+    kind: ShipKind,
+    pub health: f32,
+    /// in units per tick
+    ordered_speed: f32,
+    speed: f32,
+    /// radians clockwise from north
+    heading: f32,
+    /// points steamed round, in order
+    route: Vec<Vec3>,
+    next: usize,
+    /// so ships in company don't ride the swell in step
+    swell_phase: f32,
+    pub velocity: Vec3,
+}
+
+pub struct NavalPlugin;
+
+impl Plugin for NavalPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(setup)
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                restart_system
+                    .label(MissionRestart::Naval)
+                    .after(MissionRestart::Ground),
+            )
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(SimTick)
+                    .with_system(
+                        ship_system
+                            .after(SpatialIndexUpdate)
+                            .before(PlaneSystem::Flight),
+                    )
+                    .with_system(hit_system.label(DamageUpdate)),
+            );
+    }
+}
+
+fn add_box(
+    parent: &mut ChildBuilder,
+    meshes: &mut Assets<Mesh>,
+    material: &Handle<StandardMaterial>,
+    min: Vec3,
+    max: Vec3,
+) {
+    parent.spawn_bundle(PbrBundle {
+        mesh: meshes.add(Mesh::from(shape::Box {
+            min_x: min.x,
+            max_x: max.x,
+            min_y: min.y,
+            max_y: max.y,
+            min_z: min.z,
+            max_z: max.z,
+        })),
+        material: material.clone(),
+        ..default()
+    });
+}
+
+/// Lays the sea over the map
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.spawn_bundle(PbrBundle {
+        mesh: meshes.add(Mesh::from(shape::Plane { size: WATER_SIZE })),
+        material: materials.add(Color::rgb(0.1, 0.25, 0.4).into()),
+        transform: Transform::from_xyz(0.0, WATER_LEVEL, 0.0),
+        ..default()
+    });
+}
+
+/// Clears away the ships and puts the mission's ships back on the sea where they start, with
+/// boxes standing in for them
+#[allow(clippy::too_many_arguments)]
+fn restart_system(
+    mut commands: Commands,
+    mut events: EventReader<RestartMission>,
+    mut ids: ResMut<SimIds>,
+    mission: Res<Mission>,
+    factions: Res<Factions>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    query: Query<Entity, With<Ship>>,
+) {
+    if events.iter().count() == 0 {
+        return;
+    }
+
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let hull = materials.add(Color::rgb(0.4, 0.42, 0.45).into());
+    let deck = materials.add(Color::rgb(0.2, 0.2, 0.22).into());
+
+    for (i, placement) in mission.naval.iter().enumerate() {
+        let faction = match factions.find(&placement.faction) {
+            Some(faction) => faction,
+            None => {
+                warn!(
+                    "{:?} placed for unknown faction {}",
+                    placement.kind, placement.faction
+                );
+                continue;
+            }
+        };
+
+        let kind = placement.kind;
+        let (x, z) = placement.position;
+        let route = std::iter::once(placement.position)
+            .chain(placement.route.iter().copied())
+            .map(|(x, z)| Vec3::new(x, WATER_LEVEL, z))
+            .collect();
+        let heading = placement.heading.to_radians();
+        let speed = utils::clamp(placement.speed, 0.0, kind.max_speed());
+
+        let mut ship = commands.spawn_bundle((
+            Transform::from_xyz(x, WATER_LEVEL, z).with_rotation(Quat::from_rotation_y(-heading)),
+            GlobalTransform::identity(),
+        ));
+        ship.insert(ids.allocate()).insert(faction).insert(Ship {
+            kind,
+            health: kind.health(),
+            ordered_speed: speed,
+            speed,
+            heading,
+            route,
+            next: 1,
+            swell_phase: i as f32 * 1.7,
+            velocity: Vec3::ZERO,
+        });
+        if let Some(radar) = kind.radar() {
+            ship.insert(radar);
+        }
+        if kind == ShipKind::Destroyer {
+            ship.insert(SamSite::loaded());
+        }
+
+        // the hull runs a little below the waterline so the swell doesn't show daylight under it
+        let size = kind.size();
+        let half = Vec3::new(size.x / 2.0, 0.0, size.z / 2.0);
+        if kind == ShipKind::Carrier {
+            ship.insert(FlightDeck {
+                height: size.y + 1.0,
+                half_width: half.x,
+                half_length: half.z,
+            });
+        }
+        ship.with_children(|parent| {
+            add_box(
+                parent,
+                &mut meshes,
+                &hull,
+                Vec3::new(-half.x, -size.y / 2.0, -half.z),
+                Vec3::new(half.x, size.y, half.z),
+            );
+            match kind {
+                ShipKind::Carrier => {
+                    // flight deck, and the island on the starboard side
+                    add_box(
+                        parent,
+                        &mut meshes,
+                        &deck,
+                        Vec3::new(-half.x, size.y, -half.z),
+                        Vec3::new(half.x, size.y + 1.0, half.z),
+                    );
+                    add_box(
+                        parent,
+                        &mut meshes,
+                        &hull,
+                        Vec3::new(half.x - 15.0, size.y + 1.0, -20.0),
+                        Vec3::new(half.x - 5.0, size.y + 30.0, 20.0),
+                    );
+                }
+                ShipKind::Destroyer | ShipKind::Cargo => {
+                    add_box(
+                        parent,
+                        &mut meshes,
+                        &hull,
+                        Vec3::new(-half.x * 0.6, size.y, -half.z * 0.3),
+                        Vec3::new(half.x * 0.6, size.y * 2.0, 0.0),
+                    );
+                }
+            }
+        });
+    }
+}
+
+/// Steers each ship round its route, brings it up to its ordered speed, and rides it on the swell
+fn ship_system(clock: Res<SimClock>, mut query: Query<(&mut Transform, &mut Ship)>) {
+    for (mut transform, mut ship) in query.iter_mut() {
+        let position = transform.translation;
+
+        // a route of one point is just where the ship started, it holds its heading
+        let mut turn = 0.0;
+        if ship.route.len() > 1 {
+            let point = ship.route[ship.next];
+            let offset = Vec3::new(point.x - position.x, 0.0, point.z - position.z);
+            if offset.length() < ARRIVAL_DISTANCE {
+                ship.next = (ship.next + 1) % ship.route.len();
+            }
+            let wanted = offset.x.atan2(-offset.z);
+            let error = (wanted - ship.heading + TAU * 1.5).rem_euclid(TAU) - TAU / 2.0;
+            let rudder = utils::clamp(error / FULL_RUDDER_ERROR, -1.0, 1.0);
+            turn = rudder * RUDDER_RATE * ship.speed;
+        }
+        ship.heading = (ship.heading + turn).rem_euclid(TAU);
+
+        ship.speed += utils::clamp(ship.ordered_speed - ship.speed, -ACCELERATION, ACCELERATION);
+        let direction = Vec3::new(ship.heading.sin(), 0.0, -ship.heading.cos());
+        ship.velocity = direction * ship.speed;
+
+        // heave, pitch and roll on the swell, and heel outwards in the turn
+        let steadiness = (SWELL_REFERENCE_LENGTH / ship.kind.size().z).min(1.0);
+        let swell = clock.tick as f32 / SWELL_PERIOD_TICKS * TAU + ship.swell_phase;
+        let heave = SWELL_HEAVE * steadiness * swell.sin();
+        let pitch = SWELL_PITCH * steadiness * swell.cos();
+        let roll = SWELL_ROLL * steadiness * (swell * 0.7).sin() + turn * HEEL;
+
+        transform.translation.x += ship.velocity.x;
+        transform.translation.z += ship.velocity.z;
+        transform.translation.y = WATER_LEVEL + heave;
+        transform.rotation = Quat::from_rotation_y(-ship.heading)
+            * Quat::from_rotation_x(pitch)
+            * Quat::from_rotation_z(roll);
+    }
+}
+
+/// Takes the tick's hits off each ship's health, and sinks any with none left
+fn hit_system(
+    mut commands: Commands,
+    mut hits: EventReader<Hit>,
+    mut destroyed: EventWriter<SurfaceDestroyed>,
+    factions: Res<Factions>,
+    mut query: Query<(&Faction, &mut Ship)>,
+) {
+    for hit in hits.iter() {
+        let (faction, mut ship) = match query.get_mut(hit.entity) {
+            Ok(ship) => ship,
+            Err(_) => continue,
+        };
+        // already gone, from an earlier hit this tick
+        if ship.health <= 0.0 {
+            continue;
+        }
+
+        ship.health -= hit.damage;
+        if ship.health <= 0.0 {
+            info!("{} {:?} sunk", factions.name(*faction), ship.kind);
+            destroyed.send(SurfaceDestroyed { entity: hit.entity });
+            commands.entity(hit.entity).despawn_recursive();
+        }
+    }
+}
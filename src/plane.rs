@@ -1,44 +1,116 @@
-use bevy::{core::FixedTimestep, prelude::*};
+use bevy::prelude::*;
 
+use super::ai::{lod::LodFocus, sensors::Radar};
+use super::autopilot::{self, Autopilot};
+use super::damage::{AircraftDestroyed, Damage, DamageUpdate, SurfaceDestroyed};
+use super::faction::{Faction, Factions};
+use super::formation::Formation;
+use super::gamepad::{self, GamepadBindings};
+use super::gun::Gun;
+use super::landing::LandingGear;
+use super::missile::MissileRack;
+use super::mission::{MissionRestart, RestartMission};
+use super::recording::Recorder;
+use super::sim_time::{SimIds, SimTick, TIME_STEP};
+use super::spatial::SpatialIndex;
 use super::utils;
-use super::{Ally, Camera3d, Controls, Player};
+use super::{Camera3d, Controls, Player};
+
+pub const PLANE_MODEL: &str = "models/f22-raptor/scene.gltf#Scene0";
 
-const TIME_STEP: f32 = 1.0 / 60.0;
 const INITIAL_PLANE_ALTITUDE: f32 = 1000.0;
 
-const MINIMUM_THRUST: f32 = 0.0;
-const MAXIMUM_THRUST: f32 = 75.0;
+pub const MINIMUM_THRUST: f32 = 0.0;
+pub const MAXIMUM_THRUST: f32 = 75.0;
 const AFTERBURNER_THRUST: f32 = 100.0;
+/// thrust stops falling off with speed below this
+const MIN_THRUST_SPEED: f32 = 20.0;
 
 const CAMERA_X: f32 = 0.0;
 const CAMERA_Y: f32 = 5.0;
 const CAMERA_Z: f32 = 20.0;
 
-const GRAVITY: f32 = -9.81 * TIME_STEP;
+pub const GRAVITY: f32 = -9.81 * TIME_STEP;
+
+/// hostiles within this half angle of the nose can be locked
+const LOCK_CONE: f32 = 0.35;
+const LOCK_RANGE: f32 = 8000.0;
+
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PlaneSystem {
+    Controls,
+    Autopilot,
+    Flight,
+}
+
+/// Whether the pilot touched the controls this tick
+#[derive(Default)]
+pub struct PilotInput {
+    pub attitude: bool,
+    pub throttle: bool,
+}
 
 pub struct PlanePlugin;
 
 impl Plugin for PlanePlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(setup).add_system_set(
-            SystemSet::new()
-                .with_run_criteria(FixedTimestep::step(TIME_STEP as f64))
-                .with_system(controls_system)
-                .with_system(plane_system.after(controls_system)),
-        );
+        app.init_resource::<PilotInput>()
+            .add_startup_system(setup)
+            .add_system(target_lock_keys_system)
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                restart_system
+                    .label(MissionRestart::Player)
+                    .after(MissionRestart::Clock),
+            )
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(SimTick)
+                    .with_system(controls_system.label(PlaneSystem::Controls))
+                    .with_system(
+                        plane_system
+                            .label(PlaneSystem::Flight)
+                            .after(PlaneSystem::Controls)
+                            .after(PlaneSystem::Autopilot),
+                    )
+                    .with_system(target_lock_system.after(PlaneSystem::Flight))
+                    .with_system(destroyed_system.after(DamageUpdate)),
+            );
     }
 }
 
+/// The player's aircraft as it starts the mission, and again after being shot down
+fn fresh_aircraft() -> (
+    Transform,
+    Player,
+    Damage,
+    Gun,
+    MissileRack,
+    LandingGear,
+    Autopilot,
+) {
+    (
+        Transform::from_translation(Vec3::new(0.0, INITIAL_PLANE_ALTITUDE, 0.0)),
+        Player::default(),
+        Damage::default(),
+        Gun::default(),
+        MissileRack::default(),
+        LandingGear::default(),
+        // disengaged, with nothing carried over in the loops from before
+        Autopilot::with_waypoints(autopilot::city_circuit()),
+    )
+}
+
 fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let f22_raptor = asset_server.load("models/f22-raptor/scene.gltf#Scene0");
+    let f22_raptor = asset_server.load(PLANE_MODEL);
 
     commands
-        .spawn_bundle((
-            Transform::from_translation(Vec3::new(0.0, INITIAL_PLANE_ALTITUDE, 0.0)),
-            GlobalTransform::identity(),
-        ))
-        .insert(Player::default())
-        .insert(Ally)
+        .spawn_bundle(fresh_aircraft())
+        .insert(GlobalTransform::identity())
+        .insert(Formation::default())
+        .insert(Faction::PLAYER)
+        .insert(LodFocus)
+        .insert(Radar::FIGHTER)
         .with_children(|parent| {
             // center of the plane is not at 0,0 so offset slightly
             parent
@@ -53,7 +125,45 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         });
 }
 
-fn controls_system(keyboard_input: Res<Input<KeyCode>>, mut controls: ResMut<Controls>) {
+/// Puts the player back at the start of the mission, with the controls centred
+fn restart_system(
+    mut commands: Commands,
+    mut events: EventReader<RestartMission>,
+    mut ids: ResMut<SimIds>,
+    mut controls: ResMut<Controls>,
+    mut pilot_input: ResMut<PilotInput>,
+    query: Query<Entity, With<Player>>,
+) {
+    if events.iter().count() == 0 {
+        return;
+    }
+
+    *controls = Controls::default();
+    *pilot_input = PilotInput::default();
+    commands
+        .entity(query.single())
+        .insert_bundle(fresh_aircraft())
+        .insert(Formation::default())
+        .insert(ids.allocate());
+}
+
+#[derive(Default)]
+struct ThrottleLever {
+    last: Option<f32>,
+    engaged: bool,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn controls_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    bindings: Res<GamepadBindings>,
+    mut throttle_lever: Local<ThrottleLever>,
+    mut pilot_input: ResMut<PilotInput>,
+    mut controls: ResMut<Controls>,
+) {
     let mut pitch = 0.0;
     let mut roll = 0.0;
     let mut yaw = 0.0;
@@ -84,25 +194,185 @@ fn controls_system(keyboard_input: Res<Input<KeyCode>>, mut controls: ResMut<Con
         thrust -= 1.0;
     }
 
-    controls.yaw = utils::lerp(controls.yaw, yaw, 0.1);
-    controls.pitch = utils::lerp(controls.pitch, pitch, 0.1);
-    controls.roll = utils::lerp(controls.roll, roll, 0.1);
-    controls.thrust = utils::clamp(controls.thrust + thrust, MINIMUM_THRUST, AFTERBURNER_THRUST);
+    let pad = gamepad::read(&gamepads, &gamepad_axes, &gamepad_buttons, &bindings);
+
+    if let Some(pad) = &pad {
+        if pad.throttle_up {
+            thrust = 1.0;
+        }
+        if pad.throttle_down {
+            thrust = -1.0;
+        }
+    }
+
+    // analog axes are used as-is, keys are smoothed
+    let (pad_pitch, pad_roll, pad_yaw) = pad
+        .as_ref()
+        .map_or((0.0, 0.0, 0.0), |pad| (pad.pitch, pad.roll, pad.yaw));
+
+    controls.yaw = if pad_yaw != 0.0 {
+        pad_yaw
+    } else {
+        utils::lerp(controls.yaw, yaw, 0.1)
+    };
+    controls.pitch = if pad_pitch != 0.0 {
+        pad_pitch
+    } else {
+        utils::lerp(controls.pitch, pitch, 0.1)
+    };
+    controls.roll = if pad_roll != 0.0 {
+        pad_roll
+    } else {
+        utils::lerp(controls.roll, roll, 0.1)
+    };
+
+    // an absolute throttle lever takes over once it is moved,
+    // and holds its setting until the keys or buttons are used again
+    let lever = pad.as_ref().and_then(|pad| pad.throttle);
+    let lever_moved =
+        matches!((lever, throttle_lever.last), (Some(position), Some(last)) if position != last);
+    if thrust != 0.0 || lever.is_none() {
+        throttle_lever.engaged = false;
+    } else {
+        throttle_lever.engaged |= lever_moved;
+    }
+    throttle_lever.last = lever;
+
+    match lever {
+        Some(position) if throttle_lever.engaged => {
+            controls.thrust = bindings.throttle.apply_absolute(position) * AFTERBURNER_THRUST;
+        }
+        _ => {
+            controls.thrust =
+                utils::clamp(controls.thrust + thrust, MINIMUM_THRUST, AFTERBURNER_THRUST);
 
-    if thrust != 1.0 && controls.thrust > MAXIMUM_THRUST {
-        controls.thrust -= 0.5
+            if thrust != 1.0 && controls.thrust > MAXIMUM_THRUST {
+                controls.thrust -= 0.5
+            }
+        }
     }
 
     controls.airbrakes = thrust == -1.0 && controls.thrust == 0.0;
+    controls.trigger =
+        keyboard_input.pressed(KeyCode::Space) || pad.as_ref().is_some_and(|pad| pad.fire);
+    controls.launch =
+        keyboard_input.pressed(KeyCode::R) || pad.as_ref().is_some_and(|pad| pad.launch);
+    controls.launch_radar =
+        keyboard_input.pressed(KeyCode::V) || pad.as_ref().is_some_and(|pad| pad.launch_radar);
+    controls.catapult =
+        keyboard_input.pressed(KeyCode::C) || pad.as_ref().is_some_and(|pad| pad.catapult);
+
+    pilot_input.attitude = pitch != 0.0
+        || roll != 0.0
+        || yaw != 0.0
+        || pad_pitch != 0.0
+        || pad_roll != 0.0
+        || pad_yaw != 0.0;
+    // a lever left where it is doesn't take the throttle back from the autothrottle
+    pilot_input.throttle = thrust != 0.0 || lever_moved;
+}
+
+/// T asks for a lock on the next tick, where it's recorded along with the other controls
+fn target_lock_keys_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    recorder: Res<Recorder>,
+    mut controls: ResMut<Controls>,
+) {
+    // the recording asks for locks on playback
+    if let Recorder::Playing { .. } = *recorder {
+        return;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::T) {
+        controls.lock = true;
+    }
+}
+
+/// Locks the hostile closest to the nose when asked, or the next one along if already locked
+fn target_lock_system(
+    mut controls: ResMut<Controls>,
+    index: Res<SpatialIndex>,
+    factions: Res<Factions>,
+    mut query: Query<(&Transform, &Faction, &mut Player)>,
+) {
+    let (transform, faction, mut player) = query.single_mut();
+
+    // drop the lock once the target is gone
+    if let Some(target) = player.target {
+        if index.get(target).is_none() {
+            player.target = None;
+        }
+    }
+
+    if !std::mem::take(&mut controls.lock) {
+        return;
+    }
+
+    let position = transform.translation;
+    let forward = transform.forward();
+    // cosine of the angle off the nose, closest to the nose first
+    let boresight = |target: Vec3| forward.dot((target - position).normalize_or_zero());
+
+    let mut candidates: Vec<_> = index
+        .within_cone(position, forward, LOCK_CONE, LOCK_RANGE)
+        .filter(|contact| factions.hostile(*faction, contact.faction))
+        .map(|contact| (contact.entity, boresight(contact.position)))
+        .collect();
+    candidates.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+    let next = match player
+        .target
+        .and_then(|target| candidates.iter().position(|(entity, _)| *entity == target))
+    {
+        Some(current) => candidates.get(current + 1).or_else(|| candidates.first()),
+        None => candidates.first(),
+    };
+
+    player.target = next.map(|(entity, _)| *entity);
 }
 
-fn plane_system(
+/// Lets go of a lock on anything destroyed, and puts the player back at the start in a fresh
+/// aircraft once they're shot down themselves; the wreck falls on without them
+#[allow(clippy::type_complexity)]
+fn destroyed_system(
+    mut events: EventReader<AircraftDestroyed>,
+    mut surface_events: EventReader<SurfaceDestroyed>,
+    mut query: Query<(
+        Entity,
+        &mut Transform,
+        &mut Player,
+        &mut Damage,
+        &mut Gun,
+        &mut MissileRack,
+        &mut LandingGear,
+        &mut Autopilot,
+    )>,
+) {
+    let (entity, mut transform, mut player, mut damage, mut gun, mut rack, mut gear, mut autopilot) =
+        query.single_mut();
+
+    for event in surface_events.iter() {
+        if player.target == Some(event.entity) {
+            player.target = None;
+        }
+    }
+    for event in events.iter() {
+        if player.target == Some(event.entity) {
+            player.target = None;
+        }
+        if event.entity == entity {
+            (*transform, *player, *damage, *gun, *rack, *gear, *autopilot) = fresh_aircraft();
+        }
+    }
+}
+
+pub fn plane_system(
     controls: Res<Controls>,
-    mut query: Query<(&mut Transform, &mut Player), With<Player>>,
+    mut query: Query<(&mut Transform, &mut Player, &Damage), With<Player>>,
     mut camera_query: Query<&mut Transform, (With<Camera3d>, Without<Player>)>,
 ) {
     let mut camera = camera_query.single_mut();
-    let (mut player_transform, mut player) = query.single_mut();
+    let (mut player_transform, mut player, damage) = query.single_mut();
 
     let forwards = player_transform.forward();
 
@@ -111,9 +381,10 @@ fn plane_system(
         / forwards.length();
     let on_axis_speed = player.velocity.length() * axis_deviance;
 
-    let yaw = controls.yaw * 0.01 * axis_deviance;
-    let pitch = controls.pitch * 0.03 * axis_deviance;
-    let roll = controls.roll * 0.05 * axis_deviance;
+    // a shot-up tail and wings answer the stick less
+    let yaw = controls.yaw * 0.01 * axis_deviance * damage.pitch_yaw();
+    let pitch = controls.pitch * 0.03 * axis_deviance * damage.pitch_yaw();
+    let roll = controls.roll * 0.05 * axis_deviance * damage.roll();
 
     // many thanks to rchar
     let player_x = player_transform.local_x();
@@ -124,13 +395,16 @@ fn plane_system(
         * Quat::from_axis_angle(player_z, roll);
     player_transform.rotate(rot);
 
-    // thrust
-    let speed = player.velocity.length_squared();
+    // thrust, which would be unbounded standing still on the wheels
+    let speed = player
+        .velocity
+        .length_squared()
+        .max(MIN_THRUST_SPEED * MIN_THRUST_SPEED);
     let thrust = if controls.thrust > MAXIMUM_THRUST {
         MAXIMUM_THRUST + (controls.thrust - MAXIMUM_THRUST).powf(2.0)
     } else {
         controls.thrust
-    };
+    } * damage.thrust();
 
     player.velocity += (forwards * (thrust)) / speed;
 
@@ -154,18 +428,15 @@ fn plane_system(
     player.velocity += Vec3::new(0.0, GRAVITY, 0.0);
 
     // lift
+    let lift = Vec3::new(0.0, -GRAVITY, 0.0) * axis_deviance * damage.lift();
     if on_axis_speed > 30.0 {
-        player.velocity += Vec3::new(0.0, -GRAVITY, 0.0) * axis_deviance;
+        player.velocity += lift;
     } else {
-        player.velocity += Vec3::new(0.0, -GRAVITY, 0.0) * axis_deviance * (on_axis_speed / 30.0);
+        player.velocity += lift * (on_axis_speed / 30.0);
     }
 
     player_transform.translation += player.velocity;
 
-    if player_transform.translation.y < 0.0 {
-        player_transform.translation.y = 0.0;
-    }
-
     camera.translation = camera.translation.lerp(player_transform.translation, 0.2)
         + player_transform
             .rotation
@@ -0,0 +1,466 @@
+use std::{fmt::Write, fs, path::Path};
+
+use bevy::prelude::*;
+
+use super::ai::orders::RADIO_OPTIONS;
+use super::mission::{MissionOptions, RestartMission};
+use super::plane::PlaneSystem;
+use super::sim_time::{SimRng, SimRngReseed, SimTick};
+use super::terrain::HeightMap;
+use super::Controls;
+
+const RECORDING_PATH: &str = "recordings/flight.rec";
+const HEADER: &str = "flight_sim recording 8";
+
+/// Plays the recording back into the controls, or records them, once the autopilot has had its
+/// say and before anything acts on them
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RecorderUpdate;
+
+#[derive(Clone, Copy)]
+pub struct ControlsFrame {
+    pub pitch: f32,
+    pub yaw: f32,
+    pub roll: f32,
+    pub thrust: f32,
+    pub airbrakes: bool,
+    pub trigger: bool,
+    pub launch: bool,
+    pub launch_radar: bool,
+    pub gear: bool,
+    pub hook: bool,
+    pub catapult: bool,
+    pub lock: bool,
+    pub radio: Option<usize>,
+    pub formation: bool,
+}
+
+impl From<&Controls> for ControlsFrame {
+    fn from(controls: &Controls) -> Self {
+        ControlsFrame {
+            pitch: controls.pitch,
+            yaw: controls.yaw,
+            roll: controls.roll,
+            thrust: controls.thrust,
+            airbrakes: controls.airbrakes,
+            trigger: controls.trigger,
+            launch: controls.launch,
+            launch_radar: controls.launch_radar,
+            gear: controls.gear,
+            hook: controls.hook,
+            catapult: controls.catapult,
+            lock: controls.lock,
+            radio: controls.radio,
+            formation: controls.formation,
+        }
+    }
+}
+
+/// Everything needed to replay a flight tick for tick, from the start of the mission
+pub struct Recording {
+    pub seed: u64,
+    /// what the mission was started with, so it's started the same way again
+    pub options: MissionOptions,
+    pub frames: Vec<ControlsFrame>,
+}
+
+impl Recording {
+    /// f32 values are written in their shortest round-tripping form, so playback is bit exact
+    pub fn serialize(&self) -> String {
+        let mut out = String::new();
+
+        writeln!(out, "{}", HEADER).unwrap();
+        writeln!(out, "seed {}", self.seed).unwrap();
+        writeln!(
+            out,
+            "options {} {}",
+            self.options.carrier as u8,
+            // - for the mission's own number of aircraft
+            self.options
+                .stress
+                .map_or("-".to_string(), |count| count.to_string())
+        )
+        .unwrap();
+
+        for frame in &self.frames {
+            writeln!(
+                out,
+                "{} {} {} {} {} {} {} {} {} {} {} {} {} {}",
+                frame.pitch,
+                frame.yaw,
+                frame.roll,
+                frame.thrust,
+                frame.airbrakes as u8,
+                frame.trigger as u8,
+                frame.launch as u8,
+                frame.launch_radar as u8,
+                frame.gear as u8,
+                frame.hook as u8,
+                frame.catapult as u8,
+                frame.lock as u8,
+                // 0 for no call, so the calls count from 1
+                frame.radio.map_or(0, |call| call + 1),
+                frame.formation as u8
+            )
+            .unwrap();
+        }
+
+        out
+    }
+
+    pub fn parse(input: &str) -> Result<Recording, String> {
+        let mut lines = input.lines().enumerate();
+
+        match lines.next() {
+            Some((_, HEADER)) => {}
+            _ => return Err("not a flight recording".to_string()),
+        }
+
+        let seed = match lines
+            .next()
+            .and_then(|(_, line)| line.strip_prefix("seed "))
+        {
+            Some(seed) => seed.parse().map_err(|_| "invalid seed".to_string())?,
+            None => return Err("missing seed".to_string()),
+        };
+
+        let options = match lines
+            .next()
+            .and_then(|(_, line)| line.strip_prefix("options "))
+        {
+            Some(options) => {
+                let invalid = || "invalid options".to_string();
+                let (carrier, stress) = options.split_once(' ').ok_or_else(invalid)?;
+                MissionOptions {
+                    carrier: match carrier {
+                        "0" => false,
+                        "1" => true,
+                        _ => return Err(invalid()),
+                    },
+                    stress: match stress {
+                        "-" => None,
+                        count => Some(count.parse().map_err(|_| invalid())?),
+                    },
+                }
+            }
+            None => return Err("missing options".to_string()),
+        };
+
+        let mut frames = Vec::new();
+        for (index, line) in lines {
+            let invalid = || format!("invalid frame on line {}", index + 1);
+            let values = parse_floats(line, 14).ok_or_else(invalid)?;
+            let radio = match values[12] {
+                0.0 => None,
+                call if call.fract() == 0.0
+                    && (1.0..=RADIO_OPTIONS.len() as f32).contains(&call) =>
+                {
+                    Some(call as usize - 1)
+                }
+                _ => return Err(invalid()),
+            };
+            frames.push(ControlsFrame {
+                pitch: values[0],
+                yaw: values[1],
+                roll: values[2],
+                thrust: values[3],
+                airbrakes: values[4] != 0.0,
+                trigger: values[5] != 0.0,
+                launch: values[6] != 0.0,
+                launch_radar: values[7] != 0.0,
+                gear: values[8] != 0.0,
+                hook: values[9] != 0.0,
+                catapult: values[10] != 0.0,
+                lock: values[11] != 0.0,
+                radio,
+                formation: values[13] != 0.0,
+            });
+        }
+
+        Ok(Recording {
+            seed,
+            options,
+            frames,
+        })
+    }
+}
+
+fn parse_floats(line: &str, count: usize) -> Option<Vec<f32>> {
+    let values = line
+        .split_whitespace()
+        .map(|value| value.parse().ok())
+        .collect::<Option<Vec<f32>>>()?;
+
+    (values.len() == count).then_some(values)
+}
+
+/// Recording and playback both start the mission again, so that everything in it, not just the
+/// player, starts from where it does every time
+pub enum Recorder {
+    Idle,
+    /// waiting to start the mission again, then recording from its first tick
+    Armed,
+    Recording(Recording),
+    /// waiting to start the mission again the way the recording did, then playing it back
+    Loaded(Recording),
+    Playing {
+        recording: Recording,
+        tick: usize,
+    },
+}
+
+pub struct RecordingPlugin;
+
+impl Plugin for RecordingPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Recorder::Idle)
+            .add_startup_system(replay_argument_system)
+            .add_system(recorder_keys_system)
+            .add_system_to_stage(CoreStage::First, restart_system)
+            .add_system_set(
+                SystemSet::new()
+                    .with_run_criteria(SimTick)
+                    .with_system(reseed_system.label(SimRngReseed).before(RecorderUpdate))
+                    .with_system(
+                        recorder_system
+                            .label(RecorderUpdate)
+                            .after(PlaneSystem::Autopilot)
+                            .before(PlaneSystem::Flight),
+                    ),
+            );
+    }
+}
+
+/// `cargo run -- --replay <file>` plays a recording back from the start of the mission
+fn replay_argument_system(mut recorder: ResMut<Recorder>) {
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(index) = args.iter().position(|arg| arg == "--replay") {
+        match args.get(index + 1) {
+            Some(path) => start_playback(&mut recorder, path),
+            None => error!("--replay needs a recording file"),
+        }
+    }
+}
+
+fn start_playback(recorder: &mut Recorder, path: impl AsRef<Path>) {
+    let path = path.as_ref();
+    match fs::read_to_string(path)
+        .map_err(|err| err.to_string())
+        .and_then(|contents| Recording::parse(&contents))
+    {
+        Ok(recording) => {
+            info!(
+                "Replaying {} ticks from {}",
+                recording.frames.len(),
+                path.display()
+            );
+            *recorder = Recorder::Loaded(recording);
+        }
+        Err(err) => error!("Could not load recording {}: {}", path.display(), err),
+    }
+}
+
+fn save_recording(recording: &Recording) {
+    let path = Path::new(RECORDING_PATH);
+    if let Some(parent) = path.parent() {
+        if let Err(err) = fs::create_dir_all(parent) {
+            error!("Could not create {}: {}", parent.display(), err);
+            return;
+        }
+    }
+
+    match fs::write(path, recording.serialize()) {
+        Ok(()) => info!(
+            "Saved {} ticks to {}",
+            recording.frames.len(),
+            path.display()
+        ),
+        Err(err) => error!("Could not save recording: {}", err),
+    }
+}
+
+fn recorder_keys_system(keyboard_input: Res<Input<KeyCode>>, mut recorder: ResMut<Recorder>) {
+    if keyboard_input.just_pressed(KeyCode::F5) {
+        match std::mem::replace(&mut *recorder, Recorder::Idle) {
+            Recorder::Recording(recording) => save_recording(&recording),
+            Recorder::Armed => {}
+            _ => *recorder = Recorder::Armed,
+        }
+    }
+
+    if keyboard_input.just_pressed(KeyCode::F6) {
+        match *recorder {
+            Recorder::Playing { .. } | Recorder::Loaded(_) => *recorder = Recorder::Idle,
+            _ => start_playback(&mut recorder, RECORDING_PATH),
+        }
+    }
+}
+
+/// Starts the mission again for a recording or replay, once the city's height map is in, as
+/// the ground units sit on it and the AI flies over it from the first tick
+fn restart_system(
+    height_map: Res<HeightMap>,
+    mut recorder: ResMut<Recorder>,
+    mut options: ResMut<MissionOptions>,
+    mut restart: EventWriter<RestartMission>,
+) {
+    if !height_map.built() {
+        return;
+    }
+
+    match std::mem::replace(&mut *recorder, Recorder::Idle) {
+        Recorder::Armed => {
+            *recorder = Recorder::Recording(Recording {
+                seed: rand::random(),
+                options: *options,
+                frames: Vec::new(),
+            });
+        }
+        Recorder::Loaded(recording) => {
+            *options = recording.options;
+            *recorder = Recorder::Playing { recording, tick: 0 };
+        }
+        other => {
+            *recorder = other;
+            return;
+        }
+    }
+    info!("Starting the mission again");
+    restart.send(RestartMission);
+}
+
+/// Seeds the RNG from the recording on its first tick, so it owns the whole random sequence
+fn reseed_system(recorder: Res<Recorder>, mut rng: ResMut<SimRng>) {
+    match &*recorder {
+        Recorder::Recording(recording) if recording.frames.is_empty() => rng.reseed(recording.seed),
+        Recorder::Playing { recording, tick: 0 } => rng.reseed(recording.seed),
+        _ => {}
+    }
+}
+
+fn recorder_system(mut recorder: ResMut<Recorder>, mut controls: ResMut<Controls>) {
+    let finished = match &mut *recorder {
+        Recorder::Idle | Recorder::Armed | Recorder::Loaded(_) => false,
+        Recorder::Recording(recording) => {
+            recording.frames.push(ControlsFrame::from(&*controls));
+            false
+        }
+        Recorder::Playing { recording, tick } => match recording.frames.get(*tick) {
+            Some(frame) => {
+                controls.pitch = frame.pitch;
+                controls.yaw = frame.yaw;
+                controls.roll = frame.roll;
+                controls.thrust = frame.thrust;
+                controls.airbrakes = frame.airbrakes;
+                controls.trigger = frame.trigger;
+                controls.launch = frame.launch;
+                controls.launch_radar = frame.launch_radar;
+                controls.gear = frame.gear;
+                controls.hook = frame.hook;
+                controls.catapult = frame.catapult;
+                controls.lock = frame.lock;
+                controls.radio = frame.radio;
+                controls.formation = frame.formation;
+                *tick += 1;
+                false
+            }
+            None => true,
+        },
+    };
+
+    if finished {
+        info!("Replay finished");
+        *recorder = Recorder::Idle;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(pitch: f32, thrust: f32, trigger: bool) -> ControlsFrame {
+        ControlsFrame {
+            pitch,
+            yaw: -pitch / 3.0,
+            roll: 1.0 / 7.0,
+            thrust,
+            airbrakes: !trigger,
+            trigger,
+            launch: trigger,
+            launch_radar: !trigger,
+            gear: trigger,
+            hook: !trigger,
+            catapult: trigger,
+            lock: !trigger,
+            radio: trigger.then_some(5),
+            formation: trigger,
+        }
+    }
+
+    #[test]
+    fn round_trip_is_bit_exact() {
+        let recording = Recording {
+            seed: u64::MAX - 12345,
+            options: MissionOptions {
+                carrier: true,
+                stress: Some(1000),
+            },
+            frames: vec![
+                frame(0.0, 50.0, false),
+                frame(-0.333_333_34, 99.999_99, true),
+                frame(1.0e-38, 1.0e-45, false),
+            ],
+        };
+
+        let parsed = Recording::parse(&recording.serialize()).unwrap();
+
+        assert_eq!(parsed.seed, recording.seed);
+        assert_eq!(parsed.options, recording.options);
+
+        assert_eq!(parsed.frames.len(), recording.frames.len());
+        for (parsed, frame) in parsed.frames.iter().zip(&recording.frames) {
+            let floats = |frame: &ControlsFrame| {
+                [frame.pitch, frame.yaw, frame.roll, frame.thrust].map(f32::to_bits)
+            };
+            let buttons = |frame: &ControlsFrame| {
+                [
+                    frame.airbrakes,
+                    frame.trigger,
+                    frame.launch,
+                    frame.launch_radar,
+                    frame.gear,
+                    frame.hook,
+                    frame.catapult,
+                    frame.lock,
+                    frame.formation,
+                ]
+            };
+            assert_eq!(floats(parsed), floats(frame));
+            assert_eq!(buttons(parsed), buttons(frame));
+            assert_eq!(parsed.radio, frame.radio);
+        }
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(Recording::parse("flight_sim recording 1\nseed 1\n").is_err());
+        assert!(Recording::parse(&format!("{}\nseed x\n", HEADER)).is_err());
+
+        assert!(Recording::parse(&format!("{}\nseed 1\noptions 2 -\n", HEADER)).is_err());
+        assert!(Recording::parse(&format!("{}\nseed 1\noptions 0 many\n", HEADER)).is_err());
+        let none = Recording::parse(&format!("{}\nseed 1\noptions 0 -\n", HEADER)).unwrap();
+        assert_eq!(none.options, MissionOptions::default());
+
+        let start = format!("{}\nseed 1\noptions 0 -\n", HEADER);
+        assert!(Recording::parse(&format!("{}1 2 3\n", start)).is_err());
+
+        // radio calls count from 1, with 0 for none
+        let call = |call: &str| format!("{}0 0 0 50 0 0 0 0 0 0 0 0 {} 0\n", start, call);
+        assert_eq!(
+            Recording::parse(&call("6")).unwrap().frames[0].radio,
+            Some(5)
+        );
+        assert!(Recording::parse(&call("7")).is_err());
+        assert!(Recording::parse(&call("1.5")).is_err());
+    }
+}
@@ -0,0 +1,193 @@
+use bevy::{ecs::schedule::ShouldRun, prelude::*};
+use rand::{rngs::StdRng, SeedableRng};
+
+use super::mission::{MissionRestart, RestartMission};
+
+/// Length of one simulation tick in seconds of simulated time
+pub const TIME_STEP: f32 = 1.0 / 60.0;
+
+const TIME_SCALES: [f32; 7] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0];
+const REAL_TIME_INDEX: usize = 2;
+
+/// Upper bound on ticks run in one frame, so a slow frame can't snowball into slower ones
+const MAX_TICKS_PER_FRAME: u32 = 32;
+
+/// Run criteria shared by every system that advances the simulation
+#[derive(RunCriteriaLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SimTick;
+
+/// Reseeds the `SimRng` when a recording or replay starts; everything that draws from it runs
+/// after this, so the first draw always comes from the new seed
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SimRngReseed;
+
+pub struct SimClock {
+    pub paused: bool,
+    /// number of ticks simulated since the mission started
+    pub tick: u64,
+    scale_index: usize,
+    step_requested: bool,
+    accumulator: f32,
+    ticks_this_frame: u32,
+    looping: bool,
+}
+
+impl Default for SimClock {
+    fn default() -> Self {
+        SimClock {
+            paused: false,
+            tick: 0,
+            scale_index: REAL_TIME_INDEX,
+            step_requested: false,
+            accumulator: 0.0,
+            ticks_this_frame: 0,
+            looping: false,
+        }
+    }
+}
+
+impl SimClock {
+    /// simulated seconds per real second
+    pub fn scale(&self) -> f32 {
+        TIME_SCALES[self.scale_index]
+    }
+
+    pub fn slower(&mut self) {
+        self.scale_index = self.scale_index.saturating_sub(1);
+    }
+
+    pub fn faster(&mut self) {
+        self.scale_index = (self.scale_index + 1).min(TIME_SCALES.len() - 1);
+    }
+
+    pub fn real_time(&mut self) {
+        self.scale_index = REAL_TIME_INDEX;
+    }
+
+    /// advance exactly one tick while paused
+    pub fn step(&mut self) {
+        self.step_requested = true;
+    }
+}
+
+/// The only source of randomness the simulation may use, so a run can be reproduced from its seed
+pub struct SimRng {
+    pub rng: StdRng,
+}
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        SimRng {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn reseed(&mut self, seed: u64) {
+        *self = SimRng::new(seed);
+    }
+}
+
+/// Numbers what the simulation orders and staggers things by, in the order the mission spawns
+/// them. An `Entity` can't be used for this, as its id depends on everything spawned and
+/// despawned before it, so differs from one run to the next.
+#[derive(Component, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct SimId(pub u32);
+
+/// Hands out `SimId`s in spawn order, starting again with the mission
+#[derive(Default)]
+pub struct SimIds {
+    next: u32,
+}
+
+impl SimIds {
+    pub fn allocate(&mut self) -> SimId {
+        let id = SimId(self.next);
+        self.next += 1;
+        id
+    }
+}
+
+pub struct SimTimePlugin;
+
+impl Plugin for SimTimePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SimClock>()
+            .init_resource::<SimIds>()
+            .insert_resource(SimRng::new(rand::random()))
+            .add_system(sim_time_keys_system)
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                restart_system.label(MissionRestart::Clock),
+            )
+            .stage(CoreStage::Update, |stage: &mut SystemStage| {
+                stage.add_system_run_criteria(sim_tick.label(SimTick))
+            });
+    }
+}
+
+/// Runs the simulation systems once per elapsed tick of scaled time
+fn sim_tick(time: Res<Time>, mut clock: ResMut<SimClock>) -> ShouldRun {
+    // the criteria is re-evaluated after every tick, only accumulate on the first check of a frame
+    if !clock.looping {
+        clock.ticks_this_frame = 0;
+        if clock.paused {
+            clock.accumulator = 0.0;
+        } else {
+            clock.accumulator += time.delta_seconds() * clock.scale();
+        }
+    }
+
+    let run = if clock.paused {
+        !clock.looping && std::mem::take(&mut clock.step_requested)
+    } else if clock.ticks_this_frame >= MAX_TICKS_PER_FRAME {
+        clock.accumulator = 0.0;
+        false
+    } else if clock.accumulator >= TIME_STEP {
+        clock.accumulator -= TIME_STEP;
+        true
+    } else {
+        false
+    };
+
+    clock.looping = run;
+
+    if run {
+        clock.tick += 1;
+        clock.ticks_this_frame += 1;
+        ShouldRun::YesAndCheckAgain
+    } else {
+        ShouldRun::No
+    }
+}
+
+/// Counts ticks and numbers what's spawned from the start again
+fn restart_system(
+    mut events: EventReader<RestartMission>,
+    mut clock: ResMut<SimClock>,
+    mut ids: ResMut<SimIds>,
+) {
+    if events.iter().count() == 0 {
+        return;
+    }
+
+    clock.tick = 0;
+    *ids = SimIds::default();
+}
+
+fn sim_time_keys_system(keyboard_input: Res<Input<KeyCode>>, mut clock: ResMut<SimClock>) {
+    if keyboard_input.just_pressed(KeyCode::P) {
+        clock.paused = !clock.paused;
+    }
+    if keyboard_input.just_pressed(KeyCode::Period) && clock.paused {
+        clock.step();
+    }
+    if keyboard_input.just_pressed(KeyCode::LBracket) {
+        clock.slower();
+    }
+    if keyboard_input.just_pressed(KeyCode::RBracket) {
+        clock.faster();
+    }
+    if keyboard_input.just_pressed(KeyCode::Backslash) {
+        clock.real_time();
+    }
+}
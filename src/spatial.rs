@@ -0,0 +1,403 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use super::ai::sensors::Radar;
+use super::damage::Damage;
+use super::faction::Faction;
+use super::ground::GroundUnit;
+use super::naval::Ship;
+use super::plane::PlaneSystem;
+use super::sim_time::{SimId, SimTick};
+use super::{Player, AI};
+
+/// Width of a grid cell; aircraft are spread out horizontally far more than vertically,
+/// so the grid only divides the ground plane
+const CELL_SIZE: f32 = 1000.0;
+
+/// Runs before anything that queries the index, and before anything moves, so every entry is
+/// where it was at the start of the tick
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SpatialIndexUpdate;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Domain {
+    Air,
+    /// on the ground or the water
+    Surface,
+}
+
+/// A snapshot of an aircraft, ground unit or ship taken when the index was built
+#[derive(Clone, Copy)]
+pub struct SpatialEntry {
+    pub entity: Entity,
+    pub id: SimId,
+    pub faction: Faction,
+    pub domain: Domain,
+    pub position: Vec3,
+    pub forward: Vec3,
+    pub velocity: Vec3,
+    pub health: f32,
+    /// the radar it's searching with, for RWRs to pick up
+    pub radar: Option<Radar>,
+}
+
+/// Uniform grid over every aircraft, ground unit and ship, rebuilt once per tick. Entries are
+/// kept in `SimId` order, so searches find them in the same order every run.
+#[derive(Default)]
+pub struct SpatialIndex {
+    entries: Vec<SpatialEntry>,
+    cells: HashMap<IVec2, Vec<usize>>,
+    lookup: HashMap<Entity, usize>,
+}
+
+fn cell(position: Vec3) -> IVec2 {
+    IVec2::new(
+        (position.x / CELL_SIZE).floor() as i32,
+        (position.z / CELL_SIZE).floor() as i32,
+    )
+}
+
+impl SpatialIndex {
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.lookup.clear();
+        for cell in self.cells.values_mut() {
+            cell.clear();
+        }
+    }
+
+    pub fn insert(&mut self, entry: SpatialEntry) {
+        let index = self.entries.len();
+        self.cells
+            .entry(cell(entry.position))
+            .or_default()
+            .push(index);
+        self.lookup.insert(entry.entity, index);
+        self.entries.push(entry);
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&SpatialEntry> {
+        self.lookup.get(&entity).map(|&index| &self.entries[index])
+    }
+
+    fn cell_entries(&self, cell: IVec2) -> impl Iterator<Item = &SpatialEntry> + '_ {
+        self.cells
+            .get(&cell)
+            .into_iter()
+            .flatten()
+            .map(|&index| &self.entries[index])
+    }
+
+    /// Entries in the cells reaching within `radius` of `position` across the ground, and that
+    /// `wanted` doesn't rule out given the cell's offset from it and the distance to its corners
+    fn cells_within<'a>(
+        &'a self,
+        position: Vec3,
+        radius: f32,
+        wanted: impl Fn(Vec2, f32) -> bool + 'a,
+    ) -> impl Iterator<Item = &'a SpatialEntry> + 'a {
+        let min = cell(position - Vec3::splat(radius));
+        let max = cell(position + Vec3::splat(radius));
+        let centre = Vec2::new(position.x, position.z);
+        // half the diagonal, so every point in a cell is this close to its middle
+        let reach = CELL_SIZE * std::f32::consts::FRAC_1_SQRT_2;
+
+        (min.x..=max.x)
+            .flat_map(move |x| (min.y..=max.y).map(move |z| IVec2::new(x, z)))
+            .filter(move |cell| {
+                let offset = (cell.as_vec2() + 0.5) * CELL_SIZE - centre;
+                offset.length() - reach <= radius && wanted(offset, reach)
+            })
+            .flat_map(move |cell| self.cell_entries(cell))
+    }
+
+    /// Every entry within `radius` of `position`
+    pub fn within_radius(
+        &self,
+        position: Vec3,
+        radius: f32,
+    ) -> impl Iterator<Item = &SpatialEntry> + '_ {
+        let radius_squared = radius * radius;
+
+        self.cells_within(position, radius, |_, _| true)
+            .filter(move |entry| entry.position.distance_squared(position) <= radius_squared)
+    }
+
+    /// Every entry within `range` of `position` and `half_angle` radians of `direction`. Only the
+    /// cells the cone passes over are searched, so a narrow long range sweep stays cheap.
+    pub fn within_cone(
+        &self,
+        position: Vec3,
+        direction: Vec3,
+        half_angle: f32,
+        range: f32,
+    ) -> impl Iterator<Item = &SpatialEntry> + '_ {
+        let direction = direction.normalize_or_zero();
+        let min_cos = half_angle.cos();
+        let range_squared = range * range;
+
+        // how far either side of its bearing the cone reaches across the ground, unless it takes
+        // in straight up or down and so every bearing
+        let bearing = Vec2::new(direction.x, direction.z);
+        let elevation = direction.y.clamp(-1.0, 1.0).asin();
+        let spread = (half_angle + elevation.abs() < std::f32::consts::FRAC_PI_2)
+            .then(|| (half_angle.sin() / elevation.cos()).asin());
+
+        self.cells_within(position, range, move |offset, reach| {
+            let distance = offset.length();
+            match spread {
+                Some(spread) if distance > reach => {
+                    bearing.angle_between(offset).abs() <= spread + (reach / distance).asin()
+                }
+                _ => true,
+            }
+        })
+        .filter(move |entry| {
+            let offset = entry.position - position;
+            offset != Vec3::ZERO
+                && offset.length_squared() <= range_squared
+                && direction.dot(offset.normalize()) >= min_cos
+        })
+    }
+
+    /// The closest entry within `max_range` that passes `filter`
+    pub fn nearest(
+        &self,
+        position: Vec3,
+        max_range: f32,
+        filter: impl Fn(&SpatialEntry) -> bool,
+    ) -> Option<&SpatialEntry> {
+        let centre = cell(position);
+        let max_ring = (max_range / CELL_SIZE).ceil() as i32 + 1;
+
+        let mut best: Option<(&SpatialEntry, f32)> = None;
+
+        for ring in 0..=max_ring {
+            // everything in this ring is at least this far away, so a closer find can't exist
+            let ring_distance = (ring - 1).max(0) as f32 * CELL_SIZE;
+            if let Some((_, distance)) = best {
+                if ring_distance > distance {
+                    break;
+                }
+            }
+
+            for x in -ring..=ring {
+                for z in -ring..=ring {
+                    if x.abs() != ring && z.abs() != ring {
+                        continue;
+                    }
+
+                    for entry in self.cell_entries(centre + IVec2::new(x, z)) {
+                        let distance = entry.position.distance(position);
+                        if distance <= max_range
+                            && best.is_none_or(|(_, best_distance)| distance < best_distance)
+                            && filter(entry)
+                        {
+                            best = Some((entry, distance));
+                        }
+                    }
+                }
+            }
+        }
+
+        best.map(|(entry, _)| entry)
+    }
+}
+
+pub struct SpatialPlugin;
+
+impl Plugin for SpatialPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SpatialIndex>().add_system_set(
+            SystemSet::new().with_run_criteria(SimTick).with_system(
+                spatial_index_system
+                    .label(SpatialIndexUpdate)
+                    .before(PlaneSystem::Flight),
+            ),
+        );
+    }
+}
+
+/// Snapshots every aircraft, ground unit and ship; the AI reads this rather than each other's components,
+/// so it can think about all of them in parallel
+#[allow(clippy::type_complexity)]
+pub fn spatial_index_system(
+    mut index: ResMut<SpatialIndex>,
+    query: Query<(
+        Entity,
+        &SimId,
+        &Transform,
+        &Faction,
+        Option<&AI>,
+        Option<&Player>,
+        Option<&GroundUnit>,
+        Option<&Ship>,
+        Option<&Radar>,
+        Option<&Damage>,
+    )>,
+) {
+    index.clear();
+
+    let mut entries: Vec<_> = query
+        .iter()
+        .map(
+            |(entity, id, transform, faction, ai, player, ground, ship, radar, damage)| {
+                SpatialEntry {
+                    entity,
+                    id: *id,
+                    faction: *faction,
+                    domain: match (ground, ship) {
+                        (None, None) => Domain::Air,
+                        _ => Domain::Surface,
+                    },
+                    position: transform.translation,
+                    forward: transform.forward(),
+                    velocity: ai
+                        .map(|ai| ai.velocity)
+                        .or_else(|| player.map(|player| player.velocity))
+                        .or_else(|| ship.map(|ship| ship.velocity))
+                        .unwrap_or(Vec3::ZERO),
+                    health: damage
+                        .map(|damage| damage.health())
+                        .or_else(|| ground.map(|ground| ground.health))
+                        .or_else(|| ship.map(|ship| ship.health))
+                        .unwrap_or(100.0),
+                    radar: radar.copied(),
+                }
+            },
+        )
+        .collect();
+    entries.sort_unstable_by_key(|entry| entry.id);
+    for entry in entries {
+        index.insert(entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(positions: &[Vec3]) -> SpatialIndex {
+        let mut index = SpatialIndex::default();
+        for (id, &position) in positions.iter().enumerate() {
+            index.insert(SpatialEntry {
+                entity: Entity::from_raw(id as u32),
+                id: SimId(id as u32),
+                faction: Faction(0),
+                domain: Domain::Air,
+                position,
+                forward: -Vec3::Z,
+                velocity: Vec3::ZERO,
+                health: 100.0,
+                radar: None,
+            });
+        }
+        index
+    }
+
+    fn ids<'a>(entries: impl Iterator<Item = &'a SpatialEntry>) -> Vec<u32> {
+        let mut ids: Vec<_> = entries.map(|entry| entry.entity.id()).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn radius_reaches_across_cells() {
+        let index = index(&[
+            // either side of the cell boundaries at 0 and 1000
+            Vec3::new(999.0, 0.0, 0.0),
+            Vec3::new(1001.0, 0.0, 0.0),
+            Vec3::new(-1.0, 0.0, -1.0),
+            Vec3::new(500.0, 0.0, 1999.0),
+            // too far, in a cell the search covers
+            Vec3::new(1999.0, 0.0, 1999.0),
+            // in range horizontally but not vertically
+            Vec3::new(1000.0, 600.0, 0.0),
+        ]);
+
+        let found = ids(index.within_radius(Vec3::new(1000.0, 0.0, 0.0), 500.0));
+        assert_eq!(found, vec![0, 1]);
+
+        let found = ids(index.within_radius(Vec3::new(500.0, 0.0, 500.0), 1500.0));
+        assert_eq!(found, vec![0, 1, 2, 3, 5]);
+
+        assert!(index.within_radius(Vec3::ZERO, 0.5).next().is_none());
+    }
+
+    #[test]
+    fn cone_only_finds_entries_ahead() {
+        let index = index(&[
+            Vec3::new(0.0, 0.0, -1500.0),
+            Vec3::new(300.0, 0.0, -1500.0),
+            Vec3::new(1500.0, 0.0, -300.0),
+            Vec3::new(0.0, 0.0, 1500.0),
+            Vec3::new(0.0, 0.0, -3500.0),
+            // at the apex, with no direction to it
+            Vec3::ZERO,
+        ]);
+
+        let found = ids(index.within_cone(Vec3::ZERO, -Vec3::Z * 10.0, 0.3, 3000.0));
+        assert_eq!(found, vec![0, 1]);
+
+        // looking straight down takes in every bearing
+        let found = ids(index.within_cone(Vec3::new(0.0, 2000.0, 0.0), -Vec3::Y, 0.8, 3000.0));
+        assert_eq!(found, vec![0, 1, 2, 3, 5]);
+    }
+
+    #[test]
+    fn cone_matches_a_brute_force_search() {
+        // a scattering of entries over a few cells each way, at different heights
+        let positions: Vec<Vec3> = (0..400)
+            .map(|i| {
+                let i = i as f32;
+                Vec3::new(
+                    (i * 7.31).sin() * 4000.0,
+                    (i * 3.17).cos() * 1500.0,
+                    (i * 5.43).cos() * 4000.0,
+                )
+            })
+            .collect();
+        let index = index(&positions);
+
+        let position = Vec3::new(120.0, 300.0, -80.0);
+        for (direction, half_angle) in [
+            (Vec3::new(1.0, 0.0, 0.2), 0.3_f32),
+            (Vec3::new(-0.3, 0.6, -1.0), 1.0),
+            (Vec3::new(0.2, -0.9, 0.1), 0.5),
+            (Vec3::new(0.0, 0.0, 1.0), 2.5),
+        ] {
+            let expected: Vec<u32> = positions
+                .iter()
+                .enumerate()
+                .filter(|(_, &target)| {
+                    let offset = target - position;
+                    offset.length() <= 3500.0
+                        && direction.normalize().dot(offset.normalize()) >= half_angle.cos()
+                })
+                .map(|(id, _)| id as u32)
+                .collect();
+            let found = ids(index.within_cone(position, direction, half_angle, 3500.0));
+            assert_eq!(found, expected, "looking along {:?}", direction);
+        }
+    }
+
+    #[test]
+    fn nearest_looks_past_closer_cells() {
+        let index = index(&[
+            // in the same cell, but further than the one over the boundary
+            Vec3::new(100.0, 0.0, 100.0),
+            Vec3::new(-20.0, 0.0, 950.0),
+            Vec3::new(-2500.0, 0.0, 900.0),
+        ]);
+        let position = Vec3::new(950.0, 0.0, 950.0);
+        let nearest = |range, filter: fn(&SpatialEntry) -> bool| {
+            index
+                .nearest(position, range, filter)
+                .map(|entry| entry.entity.id())
+        };
+
+        assert_eq!(nearest(5000.0, |_| true), Some(1));
+        assert_eq!(nearest(5000.0, |entry| entry.entity.id() != 1), Some(0));
+        assert_eq!(nearest(5000.0, |entry| entry.position.x < -1000.0), Some(2));
+        assert_eq!(nearest(900.0, |entry| entry.entity.id() != 1), None);
+    }
+}
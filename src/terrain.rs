@@ -0,0 +1,198 @@
+use bevy::{
+    prelude::*,
+    render::mesh::{Indices, VertexAttributeValues},
+};
+
+/// the height map covers this far from the origin on each axis, beyond it the ground is flat
+const EXTENT: f32 = 20000.0;
+const CELL_SIZE: f32 = 100.0;
+const CELLS: usize = (EXTENT * 2.0 / CELL_SIZE) as usize;
+
+/// Root of the city scene, whose meshes are sampled into the height map
+#[derive(Component)]
+pub struct Cityscape;
+
+/// Highest point of the terrain and buildings in each cell of a grid over the map
+pub struct HeightMap {
+    heights: Vec<f32>,
+    /// top of the tallest building, above which nothing can block the view
+    highest: f32,
+    built: bool,
+}
+
+impl Default for HeightMap {
+    fn default() -> Self {
+        HeightMap {
+            heights: vec![0.0; CELLS * CELLS],
+            highest: 0.0,
+            built: false,
+        }
+    }
+}
+
+impl HeightMap {
+    /// whether the city has loaded and been measured yet; until then the ground is flat
+    pub fn built(&self) -> bool {
+        self.built
+    }
+
+    fn cell(x: f32, z: f32) -> Option<usize> {
+        let column = ((x + EXTENT) / CELL_SIZE).floor();
+        let row = ((z + EXTENT) / CELL_SIZE).floor();
+        if column < 0.0 || row < 0.0 || column >= CELLS as f32 || row >= CELLS as f32 {
+            return None;
+        }
+        Some(row as usize * CELLS + column as usize)
+    }
+
+    /// height of the ground or any building under `position`
+    pub fn height(&self, position: Vec3) -> f32 {
+        HeightMap::cell(position.x, position.z).map_or(0.0, |cell| self.heights[cell])
+    }
+
+    /// whether `position` is over the city rather than the sea beyond it
+    pub fn on_land(&self, position: Vec3) -> bool {
+        HeightMap::cell(position.x, position.z).is_some()
+    }
+
+    /// whether nothing on the ground stands between `a` and `b`
+    pub fn line_of_sight(&self, a: Vec3, b: Vec3) -> bool {
+        if a.y.min(b.y) > self.highest {
+            return true;
+        }
+        let steps = (Vec2::new(b.x - a.x, b.z - a.z).length() / CELL_SIZE).ceil() as usize;
+        (1..steps).all(|step| {
+            let point = a.lerp(b, step as f32 / steps as f32);
+            point.y > self.height(point)
+        })
+    }
+
+    fn raise(&mut self, x: f32, z: f32, height: f32) {
+        if let Some(cell) = HeightMap::cell(x, z) {
+            self.heights[cell] = self.heights[cell].max(height);
+            self.highest = self.highest.max(height);
+        }
+    }
+
+    /// Raises every cell the triangle covers to the triangle's height at the cell's centre
+    fn add_triangle(&mut self, a: Vec3, b: Vec3, c: Vec3) {
+        // triangles smaller than a cell may miss every centre, so their corners count too
+        for vertex in [a, b, c] {
+            self.raise(vertex.x, vertex.z, vertex.y);
+        }
+
+        let area = (b.x - a.x) * (c.z - a.z) - (c.x - a.x) * (b.z - a.z);
+        if area.abs() < f32::EPSILON {
+            return;
+        }
+
+        let min = a.min(b).min(c).max(Vec3::splat(-EXTENT));
+        let max = a.max(b).max(c).min(Vec3::splat(EXTENT));
+        let first_column = ((min.x + EXTENT) / CELL_SIZE).floor() as i32;
+        let last_column = ((max.x + EXTENT) / CELL_SIZE).floor() as i32;
+        let first_row = ((min.z + EXTENT) / CELL_SIZE).floor() as i32;
+        let last_row = ((max.z + EXTENT) / CELL_SIZE).floor() as i32;
+
+        for row in first_row..=last_row {
+            for column in first_column..=last_column {
+                let x = (column as f32 + 0.5) * CELL_SIZE - EXTENT;
+                let z = (row as f32 + 0.5) * CELL_SIZE - EXTENT;
+
+                // barycentric weights of the cell centre
+                let u = ((b.x - x) * (c.z - z) - (c.x - x) * (b.z - z)) / area;
+                let v = ((c.x - x) * (a.z - z) - (a.x - x) * (c.z - z)) / area;
+                let w = 1.0 - u - v;
+                if u >= 0.0 && v >= 0.0 && w >= 0.0 {
+                    self.raise(x, z, a.y * u + b.y * v + c.y * w);
+                }
+            }
+        }
+    }
+}
+
+pub struct TerrainPlugin;
+
+impl Plugin for TerrainPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HeightMap>()
+            .add_system(height_map_system);
+    }
+}
+
+fn is_city_mesh(
+    mut entity: Entity,
+    parents: &Query<&Parent>,
+    cityscape: &Query<(), With<Cityscape>>,
+) -> bool {
+    loop {
+        if cityscape.get(entity).is_ok() {
+            return true;
+        }
+        match parents.get(entity) {
+            Ok(parent) => entity = parent.0,
+            Err(_) => return false,
+        }
+    }
+}
+
+/// Samples the city into the height map once its scene has spawned and its meshes have loaded
+fn height_map_system(
+    mut height_map: ResMut<HeightMap>,
+    mut last_count: Local<usize>,
+    meshes: Res<Assets<Mesh>>,
+    mesh_query: Query<(Entity, &Handle<Mesh>, &GlobalTransform)>,
+    parents: Query<&Parent>,
+    cityscape: Query<(), With<Cityscape>>,
+) {
+    if height_map.built {
+        return;
+    }
+
+    let city_meshes: Vec<_> = mesh_query
+        .iter()
+        .filter(|(entity, _, _)| is_city_mesh(*entity, &parents, &cityscape))
+        .collect();
+
+    // wait a frame after the last mesh appears so its global transform has been propagated
+    let count = city_meshes.len();
+    let settled = count > 0 && count == *last_count;
+    *last_count = count;
+    if !settled
+        || city_meshes
+            .iter()
+            .any(|(_, handle, _)| meshes.get(*handle).is_none())
+    {
+        return;
+    }
+
+    for (_, handle, transform) in city_meshes {
+        let mesh = meshes.get(handle).unwrap();
+        let positions: Vec<Vec3> = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+            Some(VertexAttributeValues::Float32x3(positions)) => positions
+                .iter()
+                .map(|position| transform.mul_vec3(Vec3::from(*position)))
+                .collect(),
+            _ => {
+                warn!("City mesh has no usable vertex positions");
+                continue;
+            }
+        };
+
+        let indices: Vec<usize> = match mesh.indices() {
+            Some(Indices::U16(indices)) => indices.iter().map(|&i| i as usize).collect(),
+            Some(Indices::U32(indices)) => indices.iter().map(|&i| i as usize).collect(),
+            None => (0..positions.len()).collect(),
+        };
+
+        for triangle in indices.chunks_exact(3) {
+            height_map.add_triangle(
+                positions[triangle[0]],
+                positions[triangle[1]],
+                positions[triangle[2]],
+            );
+        }
+    }
+
+    height_map.built = true;
+    info!("Built terrain height map");
+}
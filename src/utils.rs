@@ -1,3 +1,5 @@
+#![allow(clippy::needless_return)]
+
 use bevy::prelude::*;
 
 pub fn lerp(v0: f32, v1: f32, t: f32) -> f32 {
@@ -14,17 +16,57 @@ pub fn clamp(x: f32, min: f32, max: f32) -> f32 {
     return x;
 }
 
-pub fn find_closest_target(targets: Vec<(Entity, &Transform)>, position: Vec3) -> Option<Entity> {
-    let mut closest_enemy = None;
-    let mut closest_distance = std::f32::MAX;
+/// A PID controller with the integral clamped so it can't wind up beyond full authority
+pub struct Pid {
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+    /// most output either way the caller acts on, which the integral term alone can't exceed
+    pub limit: f32,
+    integral: f32,
+    previous_error: Option<f32>,
+}
+
+impl Pid {
+    pub fn new(kp: f32, ki: f32, kd: f32, limit: f32) -> Self {
+        Pid {
+            kp,
+            ki,
+            kd,
+            limit,
+            integral: 0.0,
+            previous_error: None,
+        }
+    }
 
-    for (entity, transform) in targets.iter() {
-        let distance = (transform.translation - position).length();
-        if distance < closest_distance {
-            closest_enemy = Some(entity.clone());
-            closest_distance = distance;
+    pub fn update(&mut self, error: f32, dt: f32) -> f32 {
+        if self.ki != 0.0 {
+            let limit = self.limit / self.ki.abs();
+            self.integral = clamp(self.integral + error * dt, -limit, limit);
         }
+
+        let derivative = match self.previous_error {
+            Some(previous) => (error - previous) / dt,
+            None => 0.0,
+        };
+        self.previous_error = Some(error);
+
+        self.kp * error + self.ki * self.integral + self.kd * derivative
+    }
+
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+        self.previous_error = None;
     }
+}
+
+/// Wraps an angle in radians to the range -PI to PI
+pub fn wrap_angle(angle: f32) -> f32 {
+    let wrapped = (angle + std::f32::consts::PI).rem_euclid(std::f32::consts::TAU);
+    wrapped - std::f32::consts::PI
+}
 
-    return closest_enemy;
+/// Compass heading of a direction in radians, 0 along -Z and increasing towards +X
+pub fn heading(direction: Vec3) -> f32 {
+    direction.x.atan2(-direction.z)
 }